@@ -0,0 +1,88 @@
+use alloc::string::String;
+use core::fmt;
+
+use super::Warning;
+
+// Everything that can go wrong running a program. An index is the position of
+// the char at fault in the code it was found in, counted in chars. For a function
+// body or rerun output that's counted from the start of the body or output
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExecError {
+    // reading the source failed
+    Io(String),
+    // the source file isn't valid UTF-8
+    NonUtf8Source,
+    // ' tried to run output that isn't valid UTF-8
+    NonUtf8Output,
+    // a ']' with no '[' for it, or the other way round
+    MismatchedBrackets { index: usize },
+    UnterminatedString { index: usize },
+    UnterminatedComment { index: usize },
+    // an odd one out of the '%' or '0' pairs
+    UnmatchedPair { symbol: char, index: usize },
+    // Options::strict_ascii found a non-ASCII char outside of strings and comments
+    NonAscii { index: usize },
+    // Options::string_escapes found a backslash that doesn't start a known escape
+    InvalidEscape { index: usize },
+    // a 'B' not followed by 3 digits making 255 or less
+    InvalidByteLiteral { index: usize },
+    // '=' without a function name on both sides, found by check_commands
+    MisplacedCopy { index: usize },
+    // '1' or '7'
+    Unimplemented { command: char, index: usize },
+    // a command left out of Options::allowed_commands
+    Disallowed { command: char, index: usize },
+    // 'E' found the current cell and the one after it differ
+    AssertionFailed { index: usize, cell: u8, next: u8 },
+    // a function name that isn't defined, with Options::define_on_unknown off
+    UndefinedFunction { name: char, index: usize },
+    // '/' divided by 0, with Options::div_by_zero_error set
+    DivByZero { index: usize },
+    // printed more than Options::max_output
+    OutputLimit,
+    // ran Options::max_steps steps without finishing
+    StepLimit,
+    // '2' or '3' would go past Options::max_cells
+    TapeTooLarge,
+    // '2' or '3' couldn't allocate the bigger tape
+    OutOfMemory,
+    // '2' and '3' ran more times than Options::max_expansions
+    TooManyExpansions,
+    // 'I' picked an input that isn't there
+    NoSuchInput,
+    // Snapshot::from_bytes was given something Snapshot::to_bytes didn't write
+    BadSnapshot,
+    // a check set to Severity::Error failed
+    Warning(Warning),
+}
+
+impl fmt::Display for ExecError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "io error: {}", e),
+            Self::NonUtf8Source => write!(f, "source is not valid UTF-8"),
+            Self::NonUtf8Output => write!(f, "output is not valid UTF-8"),
+            Self::MismatchedBrackets { index } => write!(f, "mismatched brackets at {}", index),
+            Self::UnterminatedString { index } => write!(f, "unterminated string at {}", index),
+            Self::UnterminatedComment { index } => write!(f, "unterminated comment at {}", index),
+            Self::UnmatchedPair { symbol, index } => write!(f, "unmatched '{}' at {}", symbol, index),
+            Self::NonAscii { index } => write!(f, "non-ASCII character outside of a string or comment at {}", index),
+            Self::InvalidEscape { index } => write!(f, "invalid escape in string at {}", index),
+            Self::InvalidByteLiteral { index } => write!(f, "invalid byte literal at {}", index),
+            Self::MisplacedCopy { index } => write!(f, "'=' without a function name on both sides at {}", index),
+            Self::Unimplemented { command, index } => write!(f, "command '{}' is not implemented at {}", command, index),
+            Self::Disallowed { command, index } => write!(f, "command '{}' is not allowed at {}", command, index),
+            Self::AssertionFailed { index, cell, next } => write!(f, "assertion failed at {}: {} != {}", index, cell, next),
+            Self::UndefinedFunction { name, index } => write!(f, "undefined function '{}' at {}", name, index),
+            Self::DivByZero { index } => write!(f, "division by zero at {}", index),
+            Self::OutputLimit => write!(f, "output limit exceeded"),
+            Self::StepLimit => write!(f, "step limit exceeded"),
+            Self::TapeTooLarge => write!(f, "tape too large"),
+            Self::OutOfMemory => write!(f, "out of memory"),
+            Self::TooManyExpansions => write!(f, "too many tape expansions"),
+            Self::NoSuchInput => write!(f, "no such input"),
+            Self::BadSnapshot => write!(f, "invalid snapshot"),
+            Self::Warning(w) => write!(f, "{}", w.message()),
+        }
+    }
+}
@@ -0,0 +1,117 @@
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+
+// Table containing function names and contents
+#[derive(Clone)]
+pub struct FnTable {
+    funcs: BTreeMap<char, (Vec<char>, usize)>,
+    creating: BTreeSet<char>,
+    // times each function has been called, by name
+    calls: BTreeMap<char, usize>,
+}
+
+impl Default for FnTable {
+    fn default() -> Self { Self::new() }
+}
+
+impl FnTable {
+    pub fn new() -> Self {
+        Self { funcs: BTreeMap::new(), creating: BTreeSet::new(), calls: BTreeMap::new() }
+    }
+    // When a new char is encountered, add it to all active functions
+    pub fn put(&mut self, c: char) {
+        for f in &self.creating {
+            self.funcs.get_mut(f).unwrap().0.push(c)
+        }
+    }
+    // Is a function defined
+    pub fn exists(&self, c: char) -> bool {
+        self.funcs.contains_key(&c)
+    }
+    // Is a function in the process of being created
+    pub fn is_creating(&self, c: char) -> bool {
+        self.creating.contains(&c)
+    }
+    pub fn get(&self, c: char) -> Option<&(Vec<char>, usize)> {
+        if self.creating.contains(&c) { None }
+        else { self.funcs.get(&c) }
+    }
+    // Begin a new function
+    pub fn begin(&mut self, c: char, i: usize) {
+        self.funcs.insert(c, (vec![], i));
+        self.creating.insert(c);
+    }
+    // End a function
+    pub fn end(&mut self, c: char) {
+        self.creating.remove(&c);
+    }
+    // Number of finished functions, like get leaving out ones still being created
+    pub fn len(&self) -> usize {
+        self.funcs.len() - self.creating.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    // Everything in the table, for a Snapshot
+    #[allow(clippy::type_complexity)]
+    pub(crate) fn parts(&self) -> (&BTreeMap<char, (Vec<char>, usize)>, &BTreeSet<char>, &BTreeMap<char, usize>) {
+        (&self.funcs, &self.creating, &self.calls)
+    }
+    pub(crate) fn from_parts(
+        funcs: BTreeMap<char, (Vec<char>, usize)>,
+        creating: BTreeSet<char>,
+        calls: BTreeMap<char, usize>,
+    ) -> Self {
+        Self { funcs, creating, calls }
+    }
+    // Count a call to c, for uncalled
+    pub fn record_call(&mut self, c: char) {
+        *self.calls.entry(c).or_insert(0) += 1;
+    }
+    // Finished functions that haven't been called, in order of name
+    pub fn uncalled(&self) -> Vec<char> {
+        self.funcs.keys()
+            .filter(|c| !self.creating.contains(c) && !self.calls.contains_key(c))
+            .copied()
+            .collect()
+    }
+    // Returns true if there is a function being created
+    pub fn any_creating(&self) -> bool {
+        !self.creating.is_empty()
+    }
+    // bf19 source that defines every finished function again, for saving a
+    // session. A recursive call is stored as one name and written back doubled,
+    // so that needs Options::recursive_functions to read back in. Functions are
+    // written in the order they started in the original source, since a body
+    // can hold half of a pair of brackets that only matched up there
+    pub fn to_source(&self) -> String {
+        let mut funcs: Vec<_> = self.funcs.iter().collect();
+        funcs.sort_by_key(|&(&name, &(_, start))| (start, name));
+        let mut source = String::new();
+        for (&name, (body, _)) in funcs {
+            if self.creating.contains(&name) {
+                continue;
+            }
+            source.push(name);
+            for &c in body {
+                source.push(c);
+                if c == name {
+                    source.push(c);
+                }
+            }
+            source.push(name);
+        }
+        source
+    }
+    // copies one function to another, returns false if from isn't defined
+    pub fn copy_fn(&mut self, from: char, to: char) -> bool {
+        if let Some(x) = self.funcs.get(&from).cloned() {
+            self.funcs.insert(to, x);
+            true
+        } else {
+            false
+        }
+    }
+}
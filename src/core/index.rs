@@ -0,0 +1,384 @@
+use alloc::collections::BTreeMap;
+use alloc::vec;
+use alloc::vec::Vec;
+use bimap::BiMap;
+
+use super::{is_disallowed, is_reserved, partner, ExecError, Options};
+
+// Builds the BiMap between positions of opening and closing pairs of symbols one
+// char at a time, so a REPL or editor can validate source as it's typed without
+// rescanning it. build_index_table_for is this run over the whole source.
+// With strict_ascii, non-ASCII chars are only allowed inside strings and comments.
+// With string_escapes, a backslash inside a string means the next char can't close it.
+// With extensions, the 3 digits after a 'B' are its byte literal rather than commands,
+// and the 'B' is paired with the last of them so skips can step over the whole thing
+pub struct IndexBuilder {
+    map: BiMap<usize, usize>,
+    // position of the next char pushed
+    len: usize,
+    brackstack: Vec<usize>,
+    last_comment: Option<usize>,
+    last_quote: Option<usize>,
+    last_percent: Option<usize>,
+    last_zero: Option<usize>,
+    escaped: bool,
+    // position of a 'B' still reading its digits, with how many it has and their value
+    literal: Option<(usize, usize, u32)>,
+    strict_ascii: bool,
+    string_escapes: bool,
+    byte_literals: bool,
+}
+
+impl IndexBuilder {
+    // Only strict_ascii, string_escapes and extensions matter here
+    pub fn new(options: &Options) -> Self {
+        Self {
+            map: BiMap::new(),
+            len: 0,
+            brackstack: vec![],
+            last_comment: None,
+            last_quote: None,
+            last_percent: None,
+            last_zero: None,
+            escaped: false,
+            literal: None,
+            strict_ascii: options.strict_ascii,
+            string_escapes: options.string_escapes,
+            byte_literals: options.extensions,
+        }
+    }
+
+    // Append one char. On error the char is rejected and the builder is left
+    // as it was, so the caller can carry on with a different one
+    pub fn push(&mut self, c: char) -> Result<(), ExecError> {
+        let i = self.len;
+        if self.escaped {
+            self.escaped = false;
+            self.len += 1;
+            return Ok(());
+        }
+        if let Some((start, digits, value)) = self.literal {
+            let value = c.to_digit(10).map(|d| value * 10 + d).filter(|&v| v <= 255);
+            let value = value.ok_or(ExecError::InvalidByteLiteral { index: start })?;
+            self.literal = if digits == 2 {
+                self.map.insert(start, i);
+                None
+            } else {
+                Some((start, digits + 1, value))
+            };
+            self.len += 1;
+            return Ok(());
+        }
+        match (c, self.last_comment.is_none(), self.last_quote.is_none()) {
+            ('^',_,true) => match self.last_comment {
+                None => self.last_comment = Some(i),
+                Some(o) => {
+                    self.last_comment = None;
+                    self.map.insert(o, i);
+                }
+            },
+            ('"',true,_) => match self.last_quote {
+                None => self.last_quote = Some(i),
+                Some(o) => {
+                    self.last_quote = None;
+                    self.map.insert(o, i);
+                }
+            },
+            ('\\',true,false) if self.string_escapes => self.escaped = true,
+            ('%',true,true) => match self.last_percent {
+                None => self.last_percent = Some(i),
+                Some(o) => {
+                    self.last_percent = None;
+                    self.map.insert(o, i);
+                }
+            },
+            ('0',true,true) => match self.last_zero {
+                None => self.last_zero = Some(i),
+                Some(o) => {
+                    self.last_zero = None;
+                    self.map.insert(o, i);
+                }
+            },
+            ('B',true,true) if self.byte_literals => self.literal = Some((i, 0, 0)),
+            ('[',true,true) => self.brackstack.push(i),
+            (']',true,true) => {
+                let o = self.brackstack.pop().ok_or(ExecError::MismatchedBrackets { index: i })?;
+                self.map.insert(o, i);
+            },
+            (c,true,true) if self.strict_ascii && !c.is_ascii() => return Err(ExecError::NonAscii { index: i }),
+            _ => ()
+        }
+        self.len += 1;
+        Ok(())
+    }
+
+    pub fn extend(&mut self, code: &[char]) -> Result<(), ExecError> {
+        code.iter().try_for_each(|&c| self.push(c))
+    }
+
+    // Number of chars pushed so far
+    pub fn len(&self) -> usize { self.len }
+
+    pub fn is_empty(&self) -> bool { self.len == 0 }
+
+    // True when every bracket, string, comment and pair pushed so far is closed
+    pub fn is_complete(&self) -> bool {
+        self.brackstack.is_empty()
+            && self.last_comment.is_none()
+            && self.last_quote.is_none()
+            && self.last_percent.is_none()
+            && self.last_zero.is_none()
+            && self.literal.is_none()
+    }
+
+    // True when the next char pushed will be run as code rather than being
+    // part of a string, comment or byte literal
+    pub(crate) fn in_code(&self) -> bool {
+        !self.escaped && self.last_comment.is_none() && self.last_quote.is_none() && self.literal.is_none()
+    }
+
+    // Give up on a byte literal that got a bad digit, so pushing can carry on
+    // past it
+    pub(crate) fn drop_literal(&mut self) { self.literal = None; }
+
+    // How many '['s pushed so far are still open
+    pub(crate) fn depth(&self) -> usize { self.brackstack.len() }
+
+    // Errors for a '%' or '0' still waiting for its partner, and a byte literal
+    // cut short by the end of the code
+    fn unmatched_pairs(&self) -> impl Iterator<Item = ExecError> {
+        let percent = self.last_percent.map(|index| ExecError::UnmatchedPair { symbol: '%', index });
+        let zero = self.last_zero.map(|index| ExecError::UnmatchedPair { symbol: '0', index });
+        let literal = self.literal.map(|(index, _, _)| ExecError::InvalidByteLiteral { index });
+        percent.into_iter().chain(zero).chain(literal)
+    }
+
+    // Pairs closed so far
+    pub fn index_table(&self) -> &BiMap<usize, usize> { &self.map }
+
+    pub fn finish(self) -> BiMap<usize, usize> { self.map }
+}
+
+// Generate a BiMap between positions of opening and closing pairs of symbols for use later.
+// A '%' or '0' left without a partner would do nothing when run, which is never what was meant.
+// Positions count from the start of code, so this is what to use for running any
+// slice of a program on its own the way function bodies are: build the table for
+// the slice rather than shifting the whole program's. Program::compile does this
+// for a whole source, and IndexBuilder for code that arrives a char at a time
+pub fn build_index_table_for(code: &[char], options: &Options) -> Result<BiMap<usize, usize>, ExecError> {
+    let mut builder = IndexBuilder::new(options);
+    builder.extend(code)?;
+    if let Some(error) = builder.unmatched_pairs().next() {
+        return Err(error);
+    }
+    Ok(builder.finish())
+}
+
+// Look for every problem in code at once instead of stopping at the first, both
+// what compiling would reject and commands that would fail when run: '1' and '7'
+// aren't implemented (unless Options::tolerate_unimplemented), commands can be
+// left out of Options::allowed_commands, and '=' only means something between
+// two function names
+pub fn check_commands(code: &str, options: &Options) -> Result<(), Vec<ExecError>> {
+    let chars: Vec<char> = code.chars().collect();
+    let mut errors = vec![];
+    let mut builder = IndexBuilder::new(options);
+    // position after the last A=B copy, which is skipped over whole when run
+    let mut copy_end = 0;
+    for (idx, &c) in chars.iter().enumerate() {
+        if builder.in_code() && idx >= copy_end {
+            match c {
+                c if is_disallowed(c, options) => errors.push(ExecError::Disallowed { command: c, index: idx }),
+                '1' | '7' if !options.tolerate_unimplemented => errors.push(ExecError::Unimplemented { command: c, index: idx }),
+                '=' => errors.push(ExecError::MisplacedCopy { index: idx }),
+                c if !is_reserved(c, options) && chars.get(idx+1) == Some(&'=') => match chars.get(idx+2) {
+                    Some(_) => copy_end = idx + 3,
+                    None => {
+                        errors.push(ExecError::MisplacedCopy { index: idx+1 });
+                        copy_end = idx + 2;
+                    },
+                },
+                _ => (),
+            }
+        }
+        if let Err(error) = builder.push(c) {
+            errors.push(error);
+            // stand in a space for the rejected char so positions still line up
+            builder.drop_literal();
+            builder.push(' ').unwrap();
+        }
+    }
+    errors.extend(builder.unmatched_pairs());
+    if errors.is_empty() { Ok(()) } else { Err(errors) }
+}
+
+// Whether code, run with the default options, clearly never stops. Only what
+// runs straight from the start is looked at, up to the first command that could
+// end the program, call a function or change mode: a '|' there restarts it
+// forever, and so does a loop entered with a cell its body never changes.
+// Conservative, so false doesn't mean the program stops
+pub fn likely_nonterminating(code: &str) -> bool {
+    let options = Options::default();
+    let chars: Vec<char> = code.chars().collect();
+    let mut builder = IndexBuilder::new(&options);
+    // whether each char runs, rather than being inside a string or comment
+    let mut runs = Vec::with_capacity(chars.len());
+    for &c in &chars {
+        runs.push(builder.in_code());
+        if builder.push(c).is_err() {
+            return false;
+        }
+    }
+    if builder.unmatched_pairs().next().is_some() {
+        return false;
+    }
+    let jumps = gen_jump_table(&builder.finish(), chars.len());
+    let is_ignored = |c| options.ignored_chars.contains(c);
+    // Cells as long as the commands so far make them certain, and the head
+    let mut known = Some((BTreeMap::<isize, u8>::new(), 0isize));
+    let mut idx = 0;
+    while idx < chars.len() {
+        let c = chars[idx];
+        if !runs[idx] || is_ignored(c) {
+            idx += 1;
+            continue;
+        }
+        match (c, &mut known) {
+            ('|', _) => return true,
+            ('+', Some((cells, head))) => {
+                let cell = cells.entry(*head).or_insert(0);
+                *cell = cell.wrapping_add(1);
+            },
+            ('-', Some((cells, head))) => {
+                let cell = cells.entry(*head).or_insert(0);
+                *cell = cell.wrapping_sub(1);
+            },
+            ('>', Some((_, head))) => *head += 1,
+            ('<', Some((_, head))) => *head -= 1,
+            ('[', _) => {
+                let end = match partner(&jumps, idx) {
+                    Some(end) => end,
+                    None => return false,
+                };
+                if let Some((cells, head)) = &known {
+                    if cells.get(head).copied().unwrap_or(0) == 0 {
+                        idx = end + 1;
+                        continue;
+                    }
+                    // nothing in the body touches the cell, so it never becomes 0
+                    if (idx+1..end).all(|i| !runs[i] || is_ignored(chars[i]) || ".^".contains(chars[i])) {
+                        return true;
+                    }
+                } else {
+                    // it might not be entered, so nothing in it is certain to run
+                    idx = end + 1;
+                    continue;
+                }
+                known = None;
+            },
+            // can't stop the program but leave the cells unknown
+            ('<' | '>' | '+' | '-' | '{' | '}' | '(' | ')' | '*' | '/' | '!' | ',' | '~' | '"' | ']', _) => known = None,
+            ('.' | '^', _) => (),
+            _ => return false,
+        }
+        idx += 1;
+    }
+    false
+}
+
+// The deepest '[' ']' nesting in code, not counting brackets in strings or
+// comments. Meant for code still being edited, so a stray ']' is skipped and an
+// unclosed '[' counts as open to the end
+pub fn max_nesting_depth(code: &str) -> usize {
+    let mut builder = IndexBuilder::new(&Options::default());
+    let mut max = 0;
+    for c in code.chars() {
+        if builder.push(c).is_err() {
+            builder.push(' ').unwrap();
+        }
+        max = max.max(builder.depth());
+    }
+    max
+}
+
+// Marks a position in a jump table that isn't half of a pair
+pub(crate) const NO_JUMP: usize = usize::MAX;
+
+// Flatten an index table into a jump table, where jumps[i] is the position matching
+// the pair symbol at i, or NO_JUMP. Indexing this is a lot cheaper than the BiMap
+pub(crate) fn gen_jump_table(itable: &BiMap<usize, usize>, len: usize) -> Vec<usize> {
+    let mut jumps = vec![NO_JUMP; len];
+    for (&l, &r) in itable {
+        jumps[l] = r;
+        jumps[r] = l;
+    }
+    jumps
+}
+
+// Loops common enough to be worth running in one go instead of a command at a time
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Idiom {
+    // [-]
+    Clear,
+    // [->+<]
+    MoveRight,
+    // [-<+>]
+    MoveLeft,
+}
+
+impl Idiom {
+    // Commands one time round the loop runs, the ']' included
+    pub(crate) fn steps_per_round(self) -> u64 {
+        match self {
+            Self::Clear => 2,
+            Self::MoveRight | Self::MoveLeft => 5,
+        }
+    }
+}
+
+// For every '[' that starts one of the idioms exactly, with nothing else in
+// between, which one it is. Everything else is None
+pub(crate) fn gen_idiom_table(code: &[char], jumps: &[usize]) -> Vec<Option<Idiom>> {
+    code.iter().enumerate().map(|(i, &c)| {
+        if c != '[' {
+            return None;
+        }
+        match code.get(i+1..partner(jumps, i)?)? {
+            ['-'] => Some(Idiom::Clear),
+            ['-', '>', '+', '<'] => Some(Idiom::MoveRight),
+            ['-', '<', '+', '>'] => Some(Idiom::MoveLeft),
+            _ => None,
+        }
+    }).collect()
+}
+
+// Turn the inside of a string literal into bytes, handling \xNN, \n, \t, \\ and \".
+// start is the index of the first char, for errors
+pub(crate) fn unescape(chars: &[char], start: usize) -> Result<Vec<u8>, ExecError> {
+    let mut bytes = vec![];
+    let mut iter = chars.iter().copied().enumerate();
+    while let Some((i, c)) = iter.next() {
+        if c != '\\' {
+            bytes.push(c as u8);
+            continue;
+        }
+        let error = ExecError::InvalidEscape { index: start + i };
+        let mut next = || iter.next().map(|(_, c)| c);
+        match next() {
+            Some('n') => bytes.push(b'\n'),
+            Some('t') => bytes.push(b'\t'),
+            Some('\\') => bytes.push(b'\\'),
+            Some('"') => bytes.push(b'"'),
+            Some('x') => {
+                let hi = next().and_then(|c| c.to_digit(16));
+                let lo = next().and_then(|c| c.to_digit(16));
+                match (hi, lo) {
+                    (Some(hi), Some(lo)) => bytes.push((hi * 16 + lo) as u8),
+                    _ => return Err(error),
+                }
+            },
+            _ => return Err(error),
+        }
+    }
+    Ok(bytes)
+}
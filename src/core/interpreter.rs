@@ -0,0 +1,858 @@
+use alloc::boxed::Box;
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::ops::{Deref, DerefMut};
+use rand::{Rng, RngCore};
+
+use super::index::{build_index_table_for, gen_idiom_table, gen_jump_table, unescape, Idiom};
+use super::{
+    cell_round, is_disallowed, is_reserved, partner, ExecError, FnTable, Input, Options,
+    NewlineMode, Output, Program, Snapshot, StepStats, Tape, TraceEntry, Warning, BFMODE_ALLOW,
+};
+
+// Why the interpreter stopped
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HaltReason {
+    // the program ran to the end, hit '@' at the top level or 'H' anywhere
+    Finished,
+    // ',' found the input exhausted and Options::need_input is set
+    NeedInput,
+}
+
+// One piece of code being run: the program itself, a function body or rerun output.
+// These used to be recursive run() calls
+#[derive(Clone)]
+struct Frame {
+    code: Vec<char>,
+    jumps: Vec<usize>,
+    // loops that can be run in one go, see gen_idiom_table
+    idioms: Vec<Option<Idiom>>,
+    // current char to execute
+    idx: usize,
+    // bfmode triggered by '_' command
+    bfmode: bool,
+    // nice mode triggered by '6' command
+    nicemode: bool,
+    // where each char of code is in the source, for the trace. None for code
+    // that isn't from it, like rerun output
+    sources: Vec<Option<usize>>,
+}
+
+impl Frame {
+    fn new(code: Vec<char>, jumps: Vec<usize>, idioms: Vec<Option<Idiom>>, sources: Vec<Option<usize>>) -> Self {
+        Self { code, jumps, idioms, idx: 0, bfmode: false, nicemode: false, sources }
+    }
+}
+
+// Add c to the bodies being defined, remembering where in the source it came from
+fn put(fntable: &mut FnTable, bodies: &mut BTreeMap<char, Vec<Option<usize>>>, c: char, at: Option<usize>) {
+    fntable.put(c);
+    for &f in fntable.parts().1 {
+        bodies.entry(f).or_default().push(at);
+    }
+}
+
+// Write bytes to output and remember them for '. Anything past max_output is cut off
+fn print(output: &mut dyn Output, printed: &mut Vec<u8>, max_output: Option<usize>, bytes: &[u8]) -> Result<(), ExecError> {
+    let room = max_output.map_or(bytes.len(), |max| max.saturating_sub(printed.len()));
+    let fits = &bytes[..bytes.len().min(room)];
+    output.write_bytes(fits);
+    printed.extend_from_slice(fits);
+    if fits.len() < bytes.len() {
+        return Err(ExecError::OutputLimit);
+    }
+    Ok(())
+}
+
+// Position of the last char of the command at idx, which is idx itself except
+// for a 'B' byte literal, so skipping it skips its digits too
+fn command_end(code: &[char], jumps: &[usize], idx: usize) -> usize {
+    match code.get(idx) {
+        Some('B') => partner(jumps, idx).filter(|&end| end > idx).unwrap_or(idx),
+        _ => idx,
+    }
+}
+
+// The interpreter's function table, either its own or one lent by the caller
+enum FnTableRef<'a> {
+    Owned(FnTable),
+    Shared(&'a mut FnTable),
+}
+
+impl Deref for FnTableRef<'_> {
+    type Target = FnTable;
+    fn deref(&self) -> &FnTable {
+        match self {
+            Self::Owned(t) => t,
+            Self::Shared(t) => t,
+        }
+    }
+}
+
+impl DerefMut for FnTableRef<'_> {
+    fn deref_mut(&mut self) -> &mut FnTable {
+        match self {
+            Self::Owned(t) => t,
+            Self::Shared(t) => t,
+        }
+    }
+}
+
+// Runs a program one command at a time
+pub struct Interpreter<'a> {
+    tape: Tape,
+    printed: Vec<u8>,
+    fntable: FnTableRef<'a>,
+    input: &'a mut dyn Input,
+    // Options::inputs, taken out so reading doesn't borrow the options
+    inputs: Vec<Box<dyn Input + 'a>>,
+    // which input ',' and 'R' read from, 0 is input and n is inputs[n-1]
+    active_input: usize,
+    output: &'a mut dyn Output,
+    rng: &'a mut dyn RngCore,
+    options: Options<'a>,
+    // source of the top level program, printed by ';'
+    source: String,
+    // number of commands executed
+    steps: u64,
+    frames: Vec<Frame>,
+    // bytes given to feed_input, read before input
+    fed: VecDeque<u8>,
+    // how much had been printed at the last ' at each depth, for Options::dedupe_reruns
+    reruns: BTreeMap<usize, usize>,
+    // times '2' and '3' have run, for Options::max_expansions
+    expansions: usize,
+    // a '\r' NewlineMode::Lf is holding back until it sees whether '\n' follows
+    held_cr: bool,
+    // functions written in Rust, see register_native
+    natives: BTreeMap<char, Native<'a>>,
+    // Frame::sources for the body of each function defined by this interpreter
+    bodies: BTreeMap<char, Vec<Option<usize>>>,
+    // line and col of each char of source, worked out the first time the trace
+    // needs one
+    locations: Vec<(usize, usize)>,
+}
+
+// A function written in Rust that a program can call, see Interpreter::register_native
+pub type Native<'a> = Box<dyn FnMut(&mut Tape) + 'a>;
+
+impl<'a> Interpreter<'a> {
+    pub fn new(
+        program: &Program,
+        mut options: Options<'a>,
+        input: &'a mut dyn Input,
+        output: &'a mut dyn Output,
+        rng: &'a mut dyn RngCore,
+    ) -> Self {
+        Self {
+            tape: Tape::with_initial_cell(options.initial_cell),
+            printed: Vec::new(),
+            fntable: FnTableRef::Owned(FnTable::new()),
+            input,
+            inputs: core::mem::take(&mut options.inputs),
+            active_input: 0,
+            output,
+            rng,
+            options,
+            source: program.source().to_string(),
+            steps: 0,
+            frames: alloc::vec![Frame::new(
+                program.code().to_vec(),
+                program.jumps().to_vec(),
+                program.idioms().to_vec(),
+                (0..program.code().len()).map(Some).collect(),
+            )],
+            fed: VecDeque::new(),
+            reruns: BTreeMap::new(),
+            expansions: 0,
+            held_cr: false,
+            natives: BTreeMap::new(),
+            bodies: BTreeMap::new(),
+            locations: Vec::new(),
+        }
+    }
+
+    // Use a function table owned by the caller instead of a fresh one. Functions
+    // the program defines go into it and it can call any already in there. The
+    // table stays mutably borrowed until the interpreter is dropped, so to share
+    // one between interpreters run them one after the other, e.g. each holding
+    // the guard of an Arc<Mutex<FnTable>> while it runs
+    pub fn with_fntable(mut self, fntable: &'a mut FnTable) -> Self {
+        self.fntable = FnTableRef::Shared(fntable);
+        self
+    }
+
+    // An independent copy of the interpreter as it is now, e.g. to try a branch
+    // and throw it away if it goes wrong. Everything the program has done is
+    // copied, including a shared function table, but the io, rng and options
+    // can't be, so the fork gets its own. Like new(), the first of options.inputs
+    // is input number 1, and if the original had switched to an extra input the
+    // fork switches to the same number in its own
+    pub fn fork<'b>(
+        &self,
+        options: Options<'b>,
+        input: &'b mut dyn Input,
+        output: &'b mut dyn Output,
+        rng: &'b mut dyn RngCore,
+    ) -> Result<Interpreter<'b>, ExecError> {
+        let mut fork = Interpreter {
+            tape: self.tape.clone(),
+            printed: self.printed.clone(),
+            fntable: FnTableRef::Owned((*self.fntable).clone()),
+            input,
+            inputs: Vec::new(),
+            active_input: self.active_input,
+            output,
+            rng,
+            options,
+            source: self.source.clone(),
+            steps: self.steps,
+            frames: self.frames.clone(),
+            fed: self.fed.clone(),
+            reruns: self.reruns.clone(),
+            expansions: self.expansions,
+            held_cr: self.held_cr,
+            natives: BTreeMap::new(),
+            bodies: self.bodies.clone(),
+            locations: self.locations.clone(),
+        };
+        fork.inputs = core::mem::take(&mut fork.options.inputs);
+        if fork.active_input > fork.inputs.len() {
+            return Err(ExecError::NoSuchInput);
+        }
+        Ok(fork)
+    }
+
+    // Save where the program has got to, for resume to carry on from. Like fork()
+    // the io, rng, options and natives aren't included
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            source: self.source.clone(),
+            steps: self.steps,
+            printed: self.printed.clone(),
+            fed: self.fed.iter().copied().collect(),
+            active_input: self.active_input,
+            expansions: self.expansions,
+            held_cr: self.held_cr,
+            reruns: self.reruns.clone(),
+            tape: self.tape.clone(),
+            fntable: (*self.fntable).clone(),
+            frames: self.frames.iter()
+                .map(|frame| (frame.code.clone(), frame.idx, frame.bfmode, frame.nicemode, frame.sources.clone()))
+                .collect(),
+            bodies: self.bodies.clone(),
+        }
+    }
+
+    // Carry on from a snapshot as if it had never stopped, given the same kind of
+    // options it was running with. The same as fork() for the inputs
+    pub fn resume(
+        snapshot: Snapshot,
+        mut options: Options<'a>,
+        input: &'a mut dyn Input,
+        output: &'a mut dyn Output,
+        rng: &'a mut dyn RngCore,
+    ) -> Result<Self, ExecError> {
+        let mut frames = Vec::with_capacity(snapshot.frames.len());
+        for (code, idx, bfmode, nicemode, sources) in snapshot.frames {
+            let jumps = gen_jump_table(&build_index_table_for(&code, &options)?, code.len());
+            let idioms = gen_idiom_table(&code, &jumps);
+            frames.push(Frame { code, jumps, idioms, idx, bfmode, nicemode, sources });
+        }
+        let inputs = core::mem::take(&mut options.inputs);
+        if snapshot.active_input > inputs.len() {
+            return Err(ExecError::NoSuchInput);
+        }
+        Ok(Self {
+            tape: snapshot.tape,
+            printed: snapshot.printed,
+            fntable: FnTableRef::Owned(snapshot.fntable),
+            input,
+            inputs,
+            active_input: snapshot.active_input,
+            output,
+            rng,
+            options,
+            source: snapshot.source,
+            steps: snapshot.steps,
+            frames,
+            fed: snapshot.fed.into(),
+            reruns: snapshot.reruns,
+            expansions: snapshot.expansions,
+            held_cr: snapshot.held_cr,
+            natives: BTreeMap::new(),
+            bodies: snapshot.bodies,
+            locations: Vec::new(),
+        })
+    }
+
+    pub fn fntable(&self) -> &FnTable { &self.fntable }
+
+    pub fn tape(&self) -> &Tape { &self.tape }
+
+    // Whether '_' has switched on bfmode for the next command. Modes belong to
+    // the function they were switched on in, and end with it
+    pub fn is_bfmode(&self) -> bool { self.next_frame().is_some_and(|frame| frame.bfmode) }
+
+    // Whether '6' has switched on nice mode for the next command
+    pub fn is_nicemode(&self) -> bool { self.next_frame().is_some_and(|frame| frame.nicemode) }
+
+    // The frame the next command comes from. Finished frames get dropped before
+    // anything runs, so they're skipped here too
+    fn next_frame(&self) -> Option<&Frame> {
+        self.frames.iter().rev().find(|frame| frame.idx < frame.code.len())
+    }
+
+    // Everything printed so far
+    pub fn printed(&self) -> &[u8] { &self.printed }
+
+    // How many more bytes can be printed before hitting Options::max_output
+    pub fn output_remaining(&self) -> Option<usize> {
+        self.options.max_output.map(|max| max.saturating_sub(self.printed.len()))
+    }
+
+    // Give the program more input. After a NeedInput halt the ',' that ran out
+    // hasn't been executed yet (the frame's idx still points at it), so calling
+    // run() or step() again retries it with these bytes
+    pub fn feed_input(&mut self, bytes: &[u8]) {
+        self.fed.extend(bytes);
+    }
+
+    // Make calling c run f on the tape. Natives come before bf19 functions, so
+    // a program can't define or call a bf19 function named c, and c should be a
+    // function name rather than a command, which would never get this far.
+    // Natives aren't copied by fork(), and what they do isn't in a TraceEntry
+    // so replay() can't redo it
+    pub fn register_native(&mut self, c: char, f: Native<'a>) {
+        self.natives.insert(c, f);
+    }
+
+    // Step until the program halts
+    pub fn run(&mut self) -> Result<HaltReason, ExecError> {
+        let res = loop {
+            match self.step() {
+                Ok(None) => (),
+                Ok(Some(reason)) => break Ok(reason),
+                Err(e) => break Err(e),
+            }
+        };
+        self.output.flush();
+        res
+    }
+
+    // Step until the program prints something, returning the first new byte, or
+    // None if it halted first (including for HaltReason::NeedInput). Any more
+    // bytes printed by the same command, like with ';', are in printed()
+    pub fn run_until_output(&mut self) -> Result<Option<u8>, ExecError> {
+        let start = self.printed.len();
+        let res = loop {
+            match self.step() {
+                Ok(None) if self.printed.len() > start => break Ok(Some(self.printed[start])),
+                Ok(None) => (),
+                Ok(Some(_)) => break Ok(None),
+                Err(e) => break Err(e),
+            }
+        };
+        self.output.flush();
+        res
+    }
+
+    // Run the next command, returning why the program stopped if it did
+    pub fn step(&mut self) -> Result<Option<HaltReason>, ExecError> {
+        let idx = self.next_frame().map_or(0, |frame| frame.idx);
+        let steps = self.steps;
+        let mut res = self.step_inner();
+        // nothing more is coming after a held '\r', so it wasn't part of a "\r\n"
+        if self.held_cr && !matches!(res, Ok(None) | Ok(Some(HaltReason::NeedInput))) {
+            self.held_cr = false;
+            let released = print(self.output, &mut self.printed, self.options.max_output, b"\r");
+            if let Some(on_output) = &mut self.options.on_output {
+                on_output(self.steps, b'\r');
+            }
+            res = res.and_then(|reason| released.map(|_| reason));
+        }
+        if let Some(observe) = &mut self.options.observe {
+            if res.is_ok() && self.steps != steps && self.steps.is_multiple_of(self.options.observe_every) {
+                observe(&self.tape, idx);
+            }
+        }
+        res
+    }
+
+    // Start running code, the current frame continues after the char that started it
+    fn call(&mut self, code: Vec<char>, jumps: Vec<usize>, sources: Vec<Option<usize>>) {
+        if let Some(frame) = self.frames.last_mut() {
+            frame.idx += 1;
+        }
+        let idioms = gen_idiom_table(&code, &jumps);
+        self.frames.push(Frame::new(code, jumps, idioms, sources));
+    }
+
+    // ooh boy
+    fn step_inner(&mut self) -> Result<Option<HaltReason>, ExecError> {
+        // drop frames that have finished, like returning from run()
+        while let Some(frame) = self.frames.last() {
+            if frame.idx < frame.code.len() {
+                break;
+            }
+            self.frames.pop();
+        }
+        let depth = self.frames.len();
+        let frame = match self.frames.last_mut() {
+            Some(frame) => frame,
+            None => return Ok(Some(HaltReason::Finished)),
+        };
+        let mut idx = frame.idx;
+        let code = &frame.code;
+        let jumps = &frame.jumps;
+        let idioms = &frame.idioms;
+        let c = code[idx];
+        if let Some(trace) = &mut self.options.trace {
+            let message = match frame.sources[idx] {
+                Some(at) => {
+                    if self.locations.is_empty() {
+                        self.locations = self.source.chars().scan((1, 1), |next, c| {
+                            let (line, col) = *next;
+                            *next = if c == '\n' { (line + 1, 1) } else { (line, col + 1) };
+                            Some((line, col))
+                        }).collect();
+                    }
+                    let (line, col) = self.locations[at];
+                    format!("::DEBUG:: running {:?} (idx {}, line {}, col {})\n", c, at, line, col)
+                },
+                None => format!("::DEBUG:: running {:?} (idx {} of code from outside the source)\n", c, idx),
+            };
+            trace.write_bytes(message.as_bytes());
+            trace.flush();
+        }
+        if let Some(metrics) = &mut self.options.metrics {
+            if self.steps > 0 && self.steps.is_multiple_of(self.options.metrics_every) {
+                metrics(StepStats {
+                    steps: self.steps,
+                    output_bytes: self.printed.len(),
+                    tape_extent: self.tape.extent(),
+                });
+            }
+        }
+        if matches!(self.options.max_steps, Some(max) if self.steps >= max) {
+            return Err(ExecError::StepLimit);
+        }
+        self.steps += 1;
+        // process each "mode"
+        if frame.nicemode {
+            if c == '9' {
+                frame.nicemode = false;
+            } else {
+                print(self.output, &mut self.printed, self.options.max_output, b"Nice.\n")?;
+            }
+            frame.idx += 1;
+            return Ok(None);
+        } else if frame.bfmode {
+            if !BFMODE_ALLOW.contains(c) {
+                frame.idx += 1;
+                return Ok(None);
+            }
+        } else if !is_reserved(c, &self.options) {
+            if idx+2 < code.len() && code[idx+1] == '=' {
+                let fn1 = c;
+                let fn2 = code[idx+2];
+                if !self.fntable.copy_fn(fn2, fn1) {
+                    self.options.report(self.options.undefined_copy, Warning::CopyUndefined { from: fn2, to: fn1 })?;
+                }
+                match self.bodies.get(&fn2).cloned() {
+                    Some(sources) => self.bodies.insert(fn1, sources),
+                    None => self.bodies.remove(&fn1),
+                };
+                frame.idx = command_end(code, &frame.jumps, idx+2) + 1;
+                return Ok(None);
+            }
+        } else if self.fntable.any_creating() {
+            put(&mut self.fntable, &mut self.bodies, c, frame.sources[idx]);
+            frame.idx += 1;
+            return Ok(None);
+        }
+        if is_disallowed(c, &self.options) {
+            return Err(ExecError::Disallowed { command: c, index: idx });
+        }
+        let tape = &mut self.tape;
+        // feed_input only adds to the main input
+        let (input, fed): (&mut dyn Input, _) = match self.active_input {
+            0 => (&mut *self.input, &mut self.fed),
+            i => (&mut *self.inputs[i - 1], &mut VecDeque::new()),
+        };
+        let recording = self.options.record.is_some();
+        // idioms skip the steps in between, which anything watching every step would
+        // miss, and the commands in them wouldn't be checked against allowed_commands
+        let shortcuts = !recording
+            && self.options.trace.is_none()
+            && self.options.metrics.is_none()
+            && self.options.observe.is_none()
+            && self.options.allowed_commands.is_none();
+        // bytes for the TraceEntry, see there for what goes in them
+        let mut written = Vec::new();
+        // if we haven't returned yet then we are in normal mode
+        match c {
+            '>' => tape.next(),
+            '<' => tape.prev(),
+            '{' => {tape.delete_left();},
+            '}' => {tape.delete_right();},
+            '(' => tape.insert_left_initial(),
+            ')' => tape.insert_right_initial(),
+            '+' => tape.set(tape.get().wrapping_add(1)),
+            '-' => tape.set(tape.get().wrapping_sub(1)),
+            '*' => tape.set(tape.get().wrapping_mul(tape.get_next())),
+            '/' => match tape.get().checked_div(tape.get_next()) {
+                Some(n) => tape.set(n),
+                None if self.options.div_by_zero_error => return Err(ExecError::DivByZero { index: idx }),
+                None => tape.set(255),
+            },
+            '!' => match tape.get() {
+                0 => tape.set(1),
+                1 => tape.set(0),
+                _ => ()
+            },
+            '.' => {
+                let byte = match &mut self.options.output_filter {
+                    Some(filter) => filter(tape.get()),
+                    None => Some(tape.get()),
+                };
+                if let Some(byte) = byte {
+                    // only whole "\r\n" pairs are translated, a lone '\r' or '\n' is left alone
+                    let mut bytes = Vec::with_capacity(2);
+                    match self.options.newline_mode {
+                        NewlineMode::Lf => {
+                            if core::mem::take(&mut self.held_cr) && byte != b'\n' {
+                                bytes.push(b'\r');
+                            }
+                            if byte == b'\r' {
+                                self.held_cr = true;
+                            } else {
+                                bytes.push(byte);
+                            }
+                        },
+                        NewlineMode::Crlf if byte == b'\n' && self.printed.last() != Some(&b'\r') => {
+                            bytes.extend_from_slice(b"\r\n");
+                        },
+                        _ => bytes.push(byte),
+                    }
+                    print(self.output, &mut self.printed, self.options.max_output, &bytes)?;
+                    if let Some(on_output) = &mut self.options.on_output {
+                        for &b in &bytes {
+                            on_output(self.steps, b);
+                        }
+                    }
+                    if !bytes.is_empty() && self.options.output_buffering.should_flush(byte) {
+                        self.output.flush();
+                    }
+                }
+            },
+            ',' => match fed.pop_front().or_else(|| input.read_byte()) {
+                Some(b) => {
+                    tape.set(b);
+                    written.push(b);
+                },
+                None if self.options.need_input => {
+                    // don't count the ',' twice when it's retried
+                    self.steps -= 1;
+                    return Ok(Some(HaltReason::NeedInput));
+                },
+                None => {
+                    tape.set(0);
+                    written.push(0);
+                },
+            },
+            '[' => if tape.get() == 0 {
+                idx = partner(jumps, idx).ok_or(ExecError::MismatchedBrackets { index: idx })?;
+            } else if let Some(idiom) = idioms[idx].filter(|_| shortcuts) {
+                let n = tape.get();
+                tape.set(0);
+                match idiom {
+                    Idiom::Clear => (),
+                    Idiom::MoveRight => {
+                        tape.next();
+                        tape.set(tape.get().wrapping_add(n));
+                        tape.prev();
+                    },
+                    Idiom::MoveLeft => {
+                        tape.prev();
+                        tape.set(tape.get().wrapping_add(n));
+                        tape.next();
+                    },
+                }
+                // still count every step the loop would have taken, for 'C'
+                self.steps += idiom.steps_per_round() * n as u64;
+                idx = partner(jumps, idx).ok_or(ExecError::MismatchedBrackets { index: idx })?;
+            },
+            ']' => if tape.get() != 0 {
+                idx = partner(jumps, idx).ok_or(ExecError::MismatchedBrackets { index: idx })?;
+            },
+            '\\' => {
+                let mut i = idx;
+                while i < code.len() {
+                    if code[i] == ']' && partner(jumps, i).is_some() {
+                        idx = i;
+                        break;
+                    }
+                    i += 1;
+                }
+            },
+            '#' => if tape.get() != tape.get_next() {
+                idx = command_end(code, jumps, idx+1);
+            },
+            '?' => {
+                let b = if self.options.bounded_random {
+                    self.rng.gen_range(0..=tape.get())
+                } else {
+                    self.rng.gen()
+                };
+                tape.set(b);
+                written.push(b);
+            },
+            '$' => {
+                let mut right = self.rng.gen();
+                let (left_end, right_end) = tape.at_edges();
+                if self.options.random_walk_reflect && left_end && right_end {
+                    written.push(2);
+                } else {
+                    if self.options.random_walk_reflect && (right && right_end || !right && left_end) {
+                        right = !right;
+                    }
+                    if right {
+                        tape.next();
+                    } else {
+                        tape.prev();
+                    }
+                    written.push(right as u8);
+                }
+            },
+            '&' => if self.rng.gen() {
+                idx = command_end(code, jumps, idx+1);
+            },
+            // return from the current function, at the top level that ends the program
+            '@' => {
+                self.frames.pop();
+                return Ok(None);
+            },
+            '"' => {
+                let end = partner(jumps, idx).ok_or(ExecError::UnterminatedString { index: idx })?;
+                // a skip by '#' or '&' can land on the closing quote, which does nothing
+                if end > idx {
+                    let strpart = &code[(idx+1)..end];
+                    written = if self.options.string_escapes {
+                        unescape(strpart, idx+1)?
+                    } else {
+                        strpart.iter().map(|&c| c as u8).collect()
+                    };
+                    for &b in &written {
+                        tape.next();
+                        tape.set(b);
+                    }
+                    idx = end;
+                }
+            },
+            '`' => tape.set(tape.get() << 1),
+            '~' => tape.set(tape.get() >> 1),
+            '|' => {
+                frame.idx = 0;
+                return Ok(None);
+            },
+            // the source counts as output like anything else, so a later ' runs it
+            // in one write however long the source is, quines may do this a lot
+            ';' => {
+                print(self.output, &mut self.printed, self.options.max_output, self.source.as_bytes())?;
+            },
+            // the same for the end of a comment, rather than jumping back into it
+            '^' => match partner(jumps, idx).ok_or(ExecError::UnterminatedComment { index: idx })? {
+                end if end > idx => idx = end,
+                _ => (),
+            },
+            ':' => tape.set_next(tape.get()),
+            '\'' => {
+                if self.options.dedupe_reruns {
+                    let printed = self.printed.len();
+                    if self.reruns.range(..=depth).any(|(_, &len)| len == printed) {
+                        frame.idx += 1;
+                        return Ok(None);
+                    }
+                    self.reruns.insert(depth, printed);
+                }
+                let newcode: Vec<char> = core::str::from_utf8(&self.printed).map_err(|_| ExecError::NonUtf8Output)?.chars().collect();
+                let new_jumps = gen_jump_table(&build_index_table_for(&newcode, &self.options)?, newcode.len());
+                let sources = alloc::vec![None; newcode.len()];
+                self.call(newcode, new_jumps, sources);
+                return Ok(None);
+            },
+            '_' => if self.options.allow_modes {
+                frame.bfmode = !frame.bfmode
+            },
+            '%' => match partner(jumps, idx).ok_or(ExecError::UnmatchedPair { symbol: '%', index: idx })? {
+                o if o > idx && tape.get() == 0 => idx = o,
+                o if o < idx && tape.get() != 0 => idx = o,
+                _ => (),
+            },
+            // A=B copies are handled above, so this '=' is on its own or was skipped to
+            '=' => return Err(ExecError::MisplacedCopy { index: idx }),
+            '0' => idx = partner(jumps, idx).ok_or(ExecError::UnmatchedPair { symbol: '0', index: idx })?,
+            '1' | '7' if self.options.tolerate_unimplemented => (),
+            '1' => return Err(ExecError::Unimplemented { command: c, index: idx }), // TODO 1 instruction
+            '2' | '3' if matches!(self.options.max_expansions, Some(max) if self.expansions >= max) => {
+                return Err(ExecError::TooManyExpansions);
+            },
+            '2' => {
+                tape.try_expand_2(self.options.max_cells)?;
+                self.expansions += 1;
+            },
+            '3' => {
+                tape.try_expand_3(self.options.max_cells)?;
+                self.expansions += 1;
+            },
+            '4' => {
+                tape.randomize(self.rng);
+                if recording {
+                    written = tape.to_vec().0;
+                }
+            },
+            '5' => {
+                let base = match tape.get_next() {
+                    b if self.options.round_to_next && b != 0 => b,
+                    _ => 5,
+                };
+                tape.set(cell_round(tape.get(), base));
+                written.push(tape.get());
+            },
+            '6' => if self.options.allow_modes {
+                frame.nicemode = true
+            },
+            '7' => return Err(ExecError::Unimplemented { command: c, index: idx }), // TODO 7 instruction
+            '8' => {
+                tape.prev();
+                tape.prev();
+                tape.prev();
+                for _ in 0..7 {
+                    tape.set(8);
+                    tape.next();
+                }
+            },
+            '9' => (),
+            'S' if self.options.extensions => tape.swap_next(),
+            'D' if self.options.extensions => tape.set_prev(tape.get()),
+            'N' if self.options.extensions => tape.bitwise_not_all(),
+            'O' if self.options.extensions => tape.seek_origin(),
+            'R' if self.options.extensions => {
+                let fed: Vec<u8> = fed.drain(..).collect();
+                let n = tape.read_all_from(&mut &fed[..]) + tape.read_all_from(input);
+                if recording {
+                    let (cells, head) = tape.to_vec();
+                    written = cells[head+1-n..=head].to_vec();
+                }
+            },
+            'P' if self.options.extensions => {
+                let b = self.printed.last().copied().unwrap_or(0);
+                tape.set(b);
+                written.push(b);
+            },
+            'C' if self.options.extensions => {
+                // steps already counts this 'C'
+                let b = self.steps.min(255) as u8;
+                tape.set(b);
+                written.push(b);
+            },
+            'L' if self.options.extensions => tape.set(tape.left_len().min(255) as u8),
+            'A' if self.options.extensions => tape.set(tape.sum_right(tape.get() as usize)),
+            'T' if self.options.extensions => if let Some(on_trap) = &mut self.options.on_trap {
+                on_trap(tape, idx);
+            },
+            // only literals the index table accepted are paired with their last
+            // digit, not a 'B' skipped into inside a string or comment, or one
+            // compiled without extensions
+            'B' if self.options.extensions => {
+                let end = partner(jumps, idx).ok_or(ExecError::InvalidByteLiteral { index: idx })?;
+                let value = code.iter().take(end + 1).skip(idx + 1)
+                    .filter_map(|c| c.to_digit(10))
+                    .fold(0, |v, d| v * 10 + d);
+                tape.set(value as u8);
+                written.push(value as u8);
+                idx = end;
+            },
+            'Q' if self.options.extensions => tape.rotate_bits_left(),
+            'W' if self.options.extensions => tape.rotate_bits_right(),
+            'X' if self.options.extensions => tape.popcount(),
+            'U' | 'V' if self.options.extensions => if let Some(width) = self.options.grid_width {
+                if c == 'U' {
+                    tape.up(width);
+                } else {
+                    tape.down(width);
+                }
+                written.extend_from_slice(&(width as u64).to_le_bytes());
+            },
+            'E' if self.options.extensions => {
+                let (cell, next) = tape.pair();
+                if cell != next {
+                    return Err(ExecError::AssertionFailed { index: idx, cell, next });
+                }
+            },
+            'I' if self.options.extensions => {
+                let i = tape.get() as usize;
+                if i > self.inputs.len() {
+                    return Err(ExecError::NoSuchInput);
+                }
+                self.active_input = i;
+            },
+            'H' if self.options.extensions => {
+                self.frames.clear();
+                return Ok(Some(HaltReason::Finished));
+            },
+            c if self.options.ignored_chars.contains(c) => (),
+            _ => {
+                if let Some(native) = self.natives.get_mut(&c) {
+                    if let Some(on_call) = &mut self.options.on_call {
+                        on_call(c);
+                    }
+                    native(tape);
+                } else if let Some((func, _)) = self.fntable.get(c) {
+                    if let Some(on_call) = &mut self.options.on_call {
+                        on_call(c);
+                    }
+                    // the body gets its own jumps rather than an offset of the source's,
+                    // so functions still work when called from a different program
+                    let func = func.to_vec();
+                    self.fntable.record_call(c);
+                    let new_jumps = gen_jump_table(&build_index_table_for(&func, &self.options)?, func.len());
+                    // functions from a shared table may have come from another source
+                    let sources = match self.bodies.get(&c) {
+                        Some(sources) if sources.len() == func.len() => sources.clone(),
+                        _ => alloc::vec![None; func.len()],
+                    };
+                    self.call(func, new_jumps, sources);
+                    return Ok(None);
+                } else if self.fntable.is_creating(c) {
+                    if self.options.recursive_functions && code.get(idx+1) == Some(&c) {
+                        // doubled name inside its own body is a recursive call
+                        put(&mut self.fntable, &mut self.bodies, c, frame.sources[idx]);
+                        frame.idx += 2;
+                        return Ok(None);
+                    }
+                    self.fntable.end(c);
+                } else if !self.options.define_on_unknown {
+                    return Err(ExecError::UndefinedFunction { name: c, index: idx });
+                } else {
+                    self.fntable.begin(c, idx+1);
+                    self.bodies.insert(c, Vec::new());
+                    frame.idx += 1;
+                    return Ok(None);
+                }
+            },
+        }
+        // function names that got this far were ending a definition, not a command
+        let command = is_reserved(c, &self.options);
+        if let Some(record) = &mut self.options.record {
+            if command {
+                record(TraceEntry { command: c, idx: frame.idx, bytes: written });
+            }
+        }
+        frame.idx = idx + 1;
+        Ok(None)
+    }
+}
@@ -0,0 +1,250 @@
+// The parts of the interpreter that only need `alloc`. Everything that touches
+// the outside world goes through the Input/Output traits and an injected rng.
+
+use alloc::boxed::Box;
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+use rand::RngCore;
+
+mod error;
+mod fntable;
+mod index;
+mod interpreter;
+mod options;
+mod program;
+mod snapshot;
+mod tape;
+mod trace;
+
+pub use error::ExecError;
+pub use fntable::FnTable;
+pub use index::{build_index_table_for, check_commands, likely_nonterminating, max_nesting_depth, IndexBuilder};
+pub use interpreter::{HaltReason, Interpreter, Native};
+pub use options::{NewlineMode, Observer, Options, OutputBuffering, Severity, StepStats, Warning};
+pub use program::{random_program, Program};
+pub use snapshot::Snapshot;
+pub use tape::Tape;
+pub use trace::{replay, TraceEntry};
+use index::NO_JUMP;
+
+// Source of bytes for the ',' command, None means EOF
+pub trait Input {
+    fn read_byte(&mut self) -> Option<u8>;
+}
+
+// Sink for everything the program prints
+pub trait Output {
+    fn write_bytes(&mut self, bytes: &[u8]);
+    fn flush(&mut self) {}
+}
+
+impl Input for &[u8] {
+    fn read_byte(&mut self) -> Option<u8> {
+        let (b, rest) = self.split_first()?;
+        *self = rest;
+        Some(*b)
+    }
+}
+
+// Input that starts again from the beginning when it runs out, for testing
+// programs that expect endless input. Empty, it's at EOF straight away. Since it
+// never ends, the 'R' extension reading from it never stops either
+pub struct Cycling {
+    bytes: Vec<u8>,
+    pos: usize,
+}
+
+impl Cycling {
+    pub fn new(bytes: Vec<u8>) -> Self {
+        Self { bytes, pos: 0 }
+    }
+}
+
+impl Input for Cycling {
+    fn read_byte(&mut self) -> Option<u8> {
+        let b = *self.bytes.get(self.pos)?;
+        self.pos = (self.pos + 1) % self.bytes.len();
+        Some(b)
+    }
+}
+
+// Input read a line at a time, for line-oriented programs. Nothing is handed
+// out until the whole line has arrived, then ',' reads its bytes with a 0 in
+// place of the line ending ("\n" or "\r\n"), so a loop like ,[.,] reads exactly
+// one line. A last line with no ending still gets its 0. After that it's EOF
+pub struct LineBuffered<I> {
+    inner: I,
+    line: VecDeque<u8>,
+}
+
+impl<I: Input> LineBuffered<I> {
+    pub fn new(inner: I) -> Self {
+        Self { inner, line: VecDeque::new() }
+    }
+}
+
+impl<I: Input> Input for LineBuffered<I> {
+    fn read_byte(&mut self) -> Option<u8> {
+        if self.line.is_empty() {
+            let mut ended = false;
+            while let Some(b) = self.inner.read_byte() {
+                if b == b'\n' {
+                    ended = true;
+                    break;
+                }
+                self.line.push_back(b);
+            }
+            if !ended && self.line.is_empty() {
+                return None;
+            }
+            if self.line.back() == Some(&b'\r') {
+                self.line.pop_back();
+            }
+            self.line.push_back(0);
+        }
+        self.line.pop_front()
+    }
+}
+
+impl<O: Output + ?Sized> Output for &mut O {
+    fn write_bytes(&mut self, bytes: &[u8]) {
+        (**self).write_bytes(bytes)
+    }
+    fn flush(&mut self) {
+        (**self).flush()
+    }
+}
+
+impl<O: Output + ?Sized> Output for Box<O> {
+    fn write_bytes(&mut self, bytes: &[u8]) {
+        (**self).write_bytes(bytes)
+    }
+    fn flush(&mut self) {
+        (**self).flush()
+    }
+}
+
+impl Output for Vec<u8> {
+    fn write_bytes(&mut self, bytes: &[u8]) {
+        self.extend_from_slice(bytes);
+    }
+}
+
+// Output that calls a function with each byte as soon as it's printed
+pub struct FnOutput<F>(pub F);
+
+impl<F: FnMut(u8)> Output for FnOutput<F> {
+    fn write_bytes(&mut self, bytes: &[u8]) {
+        bytes.iter().for_each(|&b| (self.0)(b));
+    }
+}
+
+// Output that writes everything to both of its halves, e.g. a buffer capturing
+// the output and a terminal showing it as it's printed
+pub struct Tee<A, B>(pub A, pub B);
+
+impl<A: Output, B: Output> Output for Tee<A, B> {
+    fn write_bytes(&mut self, bytes: &[u8]) {
+        self.0.write_bytes(bytes);
+        self.1.write_bytes(bytes);
+    }
+    fn flush(&mut self) {
+        self.0.flush();
+        self.1.flush();
+    }
+}
+
+// Line and column, both from 1, of the char at index in code, e.g. to point at
+// where an ExecError happened. An index past the end gives the position just
+// after the last char
+pub fn source_location(code: impl IntoIterator<Item = char>, index: usize) -> (usize, usize) {
+    code.into_iter().take(index).fold((1, 1), |(line, col), c| match c {
+        '\n' => (line + 1, 1),
+        _ => (line, col + 1),
+    })
+}
+
+// Round a u8 to the nearest multiple of base, halfway rounds down. With base 5
+// 255 is a multiple, so 253 and 254 round up to it and nothing ever wraps
+// around to 0. Other bases round down instead of up past 255
+fn cell_round(n: u8, base: u8) -> u8 {
+    let o = n % base;
+    let n = base*(n/base);
+    if o <= base/2 {
+        n
+    } else {
+        n.checked_add(base).unwrap_or(n)
+    }
+}
+
+// Not functions, and neither is anything in Options::ignored_chars
+const RESERVED_CHARS: &str = "<>{}[]()+-*/!.,[]\\#?$&@\"`~|;^:'_%=0123456789";
+const BFMODE_ALLOW: &str = "<>+-[].,_ \n\t";
+// Not functions either, but only when Options::extensions is set
+// S: swap the current cell with the next one
+// H: halt the whole program, even from inside a function
+// R: read the rest of the input into the cells to the right
+// P: set the current cell to the last byte printed
+// D: copy the current cell to the previous one, the mirror of ':'
+// I: switch which input ',' reads from to the one numbered by the current cell
+// N: bitwise NOT every accessed cell
+// O: move the head back to the cell it started on
+// C: set the current cell to the number of commands run so far, up to 255
+// L: set the current cell to the number of accessed cells to its left, up to 255
+// A: replace the current cell, n, with the sum of the n cells to its right
+// T: call Options::on_trap, a breakpoint
+// E: stop with an error unless the current cell equals the next one
+// Q, W: rotate the current cell's bits left or right
+// U, V: move up or down a row of Options::grid_width cells
+// B: set the current cell to the 3 digit decimal byte after it, as in B065
+// X: replace the current cell with the number of 1 bits in it
+const EXTENSION_CHARS: &str = "SHRPDINOCLATEQWUVBX";
+
+fn is_reserved(c: char, options: &Options) -> bool {
+    RESERVED_CHARS.contains(c)
+        || options.ignored_chars.contains(c)
+        || (options.extensions && EXTENSION_CHARS.contains(c))
+}
+
+// A command that Options::allowed_commands leaves out. Function names and
+// ignored chars aren't commands so they're always allowed
+fn is_disallowed(c: char, options: &Options) -> bool {
+    options.allowed_commands.is_some_and(|allowed| {
+        is_reserved(c, options) && !options.ignored_chars.contains(c) && !allowed.contains(c)
+    })
+}
+
+// Position matching the pair symbol at idx
+fn partner(jumps: &[usize], idx: usize) -> Option<usize> {
+    jumps.get(idx).copied().filter(|&j| j != NO_JUMP)
+}
+
+// Run code reading from input and writing to output.
+// This is the entry point for environments without std
+pub fn exec_with(
+    code: &str,
+    options: Options,
+    input: &mut dyn Input,
+    output: &mut dyn Output,
+    rng: &mut dyn RngCore,
+) -> Result<(), ExecError> {
+    Program::compile(code, &options)?.run(options, input, output, rng)
+}
+
+// exec_with, also returning when each byte was printed as (steps run so far,
+// byte) for the first max_len bytes. Replaces any Options::on_output
+pub fn exec_timeline(
+    code: &str,
+    options: Options,
+    input: &mut dyn Input,
+    output: &mut dyn Output,
+    rng: &mut dyn RngCore,
+    max_len: usize,
+) -> Result<Vec<(u64, u8)>, ExecError> {
+    let mut timeline = Vec::new();
+    let record = |step, byte| if timeline.len() < max_len {
+        timeline.push((step, byte));
+    };
+    exec_with(code, Options { on_output: Some(Box::new(record)), ..options }, input, output, rng)?;
+    Ok(timeline)
+}
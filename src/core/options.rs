@@ -0,0 +1,253 @@
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+
+use super::{ExecError, Input, Output, Tape, TraceEntry};
+
+// Sees the tape and a position in the code, see Options::observe and on_trap
+pub type Observer<'a> = Box<dyn FnMut(&Tape, usize) + 'a>;
+
+// Interpreter settings, Options::default() behaves like plain bf19
+pub struct Options<'a> {
+    // Enable the extension commands in EXTENSION_CHARS. When off those chars
+    // are ordinary function names, like in plain bf19
+    pub extensions: bool,
+    // Normally the second occurrence of a function's name ends its definition,
+    // so a function can't call itself. With this set, a doubled name inside the
+    // body (FF) records a recursive call instead and a single F still ends it
+    pub recursive_functions: bool,
+    // When off, a name that isn't a function yet is an error instead of starting
+    // a definition, so a typo can't quietly define a new function. The program
+    // can then only call what's already in its FnTable or registered as native
+    pub define_on_unknown: bool,
+    // Reject source with non-ASCII chars outside of strings and comments, so
+    // stray unicode can't silently become a function name
+    pub strict_ascii: bool,
+    // Handle \xNN, \n, \t, \\ and \" escapes inside "strings". Off by default
+    // since plain bf19 puts a backslash in a string as is
+    pub string_escapes: bool,
+    // Make '5' round to the nearest multiple of the next cell instead of 5,
+    // still using 5 when the next cell is 0
+    pub round_to_next: bool,
+    // Stop with ExecError::DivByZero when '/' divides by 0, instead of setting
+    // the cell to 255 like plain bf19
+    pub div_by_zero_error: bool,
+    // Make '?' pick from 0 up to the current cell's value, e.g. ++++++? rolls a
+    // die from 0 to 6, rather than from the whole 0..=255
+    pub bounded_random: bool,
+    // Make '$' turn back at the ends of the accessed cells instead of going past
+    // them, so a random walk stays where the program has already been. With only
+    // one cell accessed it stays put
+    pub random_walk_reflect: bool,
+    // Skip a ' when nothing has been printed since the last one at the same
+    // depth or further out, since it would only run the same output again. That
+    // stops ' rerunning output that is just ' from recursing forever
+    pub dedupe_reruns: bool,
+    // Run '1' and '7' as no-ops instead of stopping with ExecError::Unimplemented,
+    // for programs that use them before they mean anything
+    pub tolerate_unimplemented: bool,
+    // Where to write the debug trace, one line per executed command. Kept
+    // separate from the program output so the two don't get mixed up
+    pub trace: Option<Box<dyn Output + 'a>>,
+    // Given every command as it runs, with enough to rebuild the tape from with
+    // replay(). Calls, returns and reruns don't touch the tape and aren't
+    // included, and neither is anything skipped by a mode
+    pub record: Option<Box<dyn FnMut(TraceEntry) + 'a>>,
+    // Called with the name of every function as it's called
+    pub on_call: Option<Box<dyn FnMut(char) + 'a>>,
+    // Called with every byte '.' prints, after output_filter and newline_mode,
+    // and the number of steps run so far including the '.' itself. Lines the
+    // output up against the computation, see exec_timeline
+    pub on_output: Option<Box<dyn FnMut(u64, u8) + 'a>>,
+    // Called every metrics_every steps, e.g. for a progress bar. It runs just
+    // before the next command, so a program that stops right after a multiple
+    // of metrics_every doesn't get a final call. Never called if metrics_every is 0
+    pub metrics: Option<Box<dyn FnMut(StepStats) + 'a>>,
+    pub metrics_every: u64,
+    // Called with the tape and the position of the command that just ran after
+    // every observe_every steps, e.g. to animate the tape. Unlike record it
+    // doesn't copy anything. Never called if observe_every is 0
+    pub observe: Option<Observer<'a>>,
+    pub observe_every: u64,
+    // Called with the tape and the position of the 'T' whenever the 'T'
+    // extension runs, like a breakpoint written into the source. 'T' does
+    // nothing without it
+    pub on_trap: Option<Observer<'a>>,
+    // When off, '_' (bfmode) and '6' (nicemode) do nothing so the program
+    // always stays in normal mode
+    pub allow_modes: bool,
+    // When ',' runs out of input, halt with HaltReason::NeedInput instead of
+    // reading 0, so an interactive frontend can feed more and resume
+    pub need_input: bool,
+    // Applied to every byte '.' prints, before it's written or added to the
+    // output that ' reruns. Returning None drops the byte
+    pub output_filter: Option<Box<dyn FnMut(u8) -> Option<u8> + 'a>>,
+    // Receives the warnings from checks set to Severity::Warn
+    pub warnings: Option<Box<dyn FnMut(Warning) + 'a>>,
+    // What to do when A=B copies a function B that was never defined
+    pub undefined_copy: Severity,
+    // Value of cells the program hasn't touched yet
+    pub initial_cell: u8,
+    // Most cells '2' and '3' may grow the tape to. They stop the program with an
+    // error rather than going past it, and rather than aborting if the
+    // allocation fails even without a limit
+    pub max_cells: Option<usize>,
+    // Most times '2' and '3' may run in total. A loop of them grows the tape
+    // geometrically, this catches that while the tape is still small
+    pub max_expansions: Option<usize>,
+    // Row length for the 'U' and 'V' extensions, which move up and down a grid
+    // laid out along the tape a row at a time. Without it they do nothing
+    pub grid_width: Option<usize>,
+    // More inputs for the 'I' command to switch to, e.g. one per file given on
+    // the command line. Cell value n picks inputs[n-1] and 0 picks the input
+    // the program was started with, which is where reading starts
+    pub inputs: Vec<Box<dyn Input + 'a>>,
+    pub output_buffering: OutputBuffering,
+    // Newline translation for '.', after output_filter. What ' reruns and 'P'
+    // peeks is the translated output
+    pub newline_mode: NewlineMode,
+    // Chars that do nothing instead of being function names, whitespace by
+    // default. Commands in here still run as commands
+    pub ignored_chars: &'a str,
+    // The only commands the program may use, e.g. "<>+-[].," for classic
+    // brainfuck. Running any other stops it with ExecError::Disallowed, while
+    // function names and ignored chars are fine. None allows everything
+    pub allowed_commands: Option<&'a str>,
+    // Most bytes the program may print. Output past it is cut off and the
+    // program stops with an error
+    pub max_output: Option<usize>,
+    // Most steps the program may run, counted like 'C' counts them. It stops
+    // with an error before the next command once it's reached
+    pub max_steps: Option<u64>,
+}
+
+impl Default for Options<'_> {
+    fn default() -> Self {
+        Self {
+            extensions: false,
+            recursive_functions: false,
+            define_on_unknown: true,
+            strict_ascii: false,
+            string_escapes: false,
+            bounded_random: false,
+            round_to_next: false,
+            div_by_zero_error: false,
+            random_walk_reflect: false,
+            dedupe_reruns: false,
+            tolerate_unimplemented: false,
+            trace: None,
+            record: None,
+            on_call: None,
+            on_output: None,
+            metrics: None,
+            metrics_every: 0,
+            observe: None,
+            observe_every: 0,
+            on_trap: None,
+            allow_modes: true,
+            need_input: false,
+            output_filter: None,
+            warnings: None,
+            undefined_copy: Severity::Silent,
+            initial_cell: 0,
+            max_cells: None,
+            max_expansions: None,
+            grid_width: None,
+            inputs: Vec::new(),
+            output_buffering: OutputBuffering::Unbuffered,
+            newline_mode: NewlineMode::Unchanged,
+            ignored_chars: " \n\t\r",
+            max_output: None,
+            max_steps: None,
+            allowed_commands: None,
+        }
+    }
+}
+
+impl Options<'_> {
+    // Deal with a problem the program has run into according to severity
+    pub(crate) fn report(&mut self, severity: Severity, warning: Warning) -> Result<(), ExecError> {
+        match severity {
+            Severity::Silent => Ok(()),
+            Severity::Warn => {
+                if let Some(warnings) = &mut self.warnings {
+                    warnings(warning);
+                }
+                Ok(())
+            },
+            Severity::Error => Err(ExecError::Warning(warning)),
+        }
+    }
+}
+
+// When to flush the output after '.' prints. It's always flushed once more when
+// run() returns, with an error or without
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputBuffering {
+    // after every byte, so output shows up straight away
+    Unbuffered,
+    // after every '\n', for line-oriented programs
+    LineBuffered,
+    // deferred: only once at the end, even when the run fails, fastest when
+    // nobody is watching
+    FullyBuffered,
+}
+
+impl OutputBuffering {
+    pub(crate) fn should_flush(self, byte: u8) -> bool {
+        match self {
+            Self::Unbuffered => true,
+            Self::LineBuffered => byte == b'\n',
+            Self::FullyBuffered => false,
+        }
+    }
+}
+
+// How '.' writes line endings
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NewlineMode {
+    // bytes are written as they are
+    Unchanged,
+    // every '\n' becomes "\r\n", for Windows terminals. One that already comes
+    // after a '\r' is left as it is
+    Crlf,
+    // every "\r\n" becomes "\n". A '\r' is held back until the next byte shows
+    // whether it's part of one, and one on its own is still printed
+    Lf,
+}
+
+// How seriously to take something questionable the program does
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    // carry on as if nothing happened, like plain bf19
+    Silent,
+    // pass a Warning to Options::warnings and carry on
+    Warn,
+    // stop with a runtime error
+    Error,
+}
+
+// Something questionable the program did
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Warning {
+    // A=B where B isn't a defined function, so nothing was copied
+    CopyUndefined { from: char, to: char },
+}
+
+impl Warning {
+    pub fn message(&self) -> &'static str {
+        match self {
+            Self::CopyUndefined { .. } => "copied from an undefined function",
+        }
+    }
+}
+
+// Snapshot of how far a program has got, passed to Options::metrics
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StepStats {
+    // commands executed so far, including ones skipped by bfmode/nicemode
+    pub steps: u64,
+    // bytes printed so far
+    pub output_bytes: usize,
+    // number of cells accessed so far
+    pub tape_extent: usize,
+}
@@ -0,0 +1,98 @@
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use bimap::BiMap;
+use rand::{Rng, RngCore};
+
+use super::index::{build_index_table_for, gen_idiom_table, gen_jump_table, Idiom};
+use super::{partner, ExecError, Input, Interpreter, Options, Output};
+
+// Source code that has been parsed and had all its jumps resolved, ready to run
+pub struct Program {
+    source: String,
+    code: Vec<char>,
+    index_table: BiMap<usize, usize>,
+    jumps: Vec<usize>,
+    idioms: Vec<Option<Idiom>>,
+}
+
+impl Program {
+    // Parse code, options only matter for strict_ascii, string_escapes and
+    // extensions. Run it with the same extensions setting it was compiled with,
+    // since that decides whether 'B' starts a byte literal
+    pub fn compile(code: &str, options: &Options) -> Result<Self, ExecError> {
+        let chars: Vec<char> = code.chars().collect();
+        let index_table = build_index_table_for(&chars, options)?;
+        let jumps = gen_jump_table(&index_table, chars.len());
+        let idioms = gen_idiom_table(&chars, &jumps);
+        Ok(Self { source: code.to_string(), code: chars, index_table, jumps, idioms })
+    }
+
+    // Opening and closing positions of every pair of symbols
+    pub fn index_table(&self) -> &BiMap<usize, usize> { &self.index_table }
+
+    // Position matching the pair symbol at idx, if it is one. This is what
+    // the interpreter uses, the BiMap lookup is a lot slower
+    pub fn jump(&self, idx: usize) -> Option<usize> {
+        partner(&self.jumps, idx)
+    }
+
+    // Run the program reading from input and writing to output
+    pub fn run(
+        &self,
+        options: Options,
+        input: &mut dyn Input,
+        output: &mut dyn Output,
+        rng: &mut dyn RngCore,
+    ) -> Result<(), ExecError> {
+        Interpreter::new(self, options, input, output, rng).run()?;
+        Ok(())
+    }
+
+    pub(crate) fn source(&self) -> &str { &self.source }
+    pub(crate) fn code(&self) -> &[char] { &self.code }
+    pub(crate) fn jumps(&self) -> &[usize] { &self.jumps }
+    pub(crate) fn idioms(&self) -> &[Option<Idiom>] { &self.idioms }
+}
+
+// Commands random_program picks from on their own. Leaves out the pair symbols,
+// which it adds in pairs, and '1', '7' and '=', which check_commands rejects
+const LONE_COMMANDS: &str = "<>{}()+-*/!.,\\#?$&@~|;:'_234568";
+
+// Random source of about len chars that parses and passes check_commands, for
+// seeding a fuzzer. Brackets, strings, comments and the '%' and '0' pairs all
+// get closed. It's sure to parse, not to stop or even run without an error
+pub fn random_program<R: Rng + ?Sized>(len: usize, rng: &mut R) -> String {
+    let lone: Vec<char> = LONE_COMMANDS.chars().collect();
+    let mut code = String::new();
+    // closing symbols still to come, innermost last
+    let mut open: Vec<char> = Vec::new();
+    while code.len() < len {
+        match rng.gen_range(0..10) {
+            0 if !open.is_empty() => code.push(open.pop().unwrap()),
+            1 => {
+                code.push('[');
+                open.push(']');
+            },
+            // a second '%' or '0' would close the open one instead of nesting
+            2 => for symbol in ['%', '0'] {
+                if !open.contains(&symbol) {
+                    code.push(symbol);
+                    open.push(symbol);
+                    break;
+                }
+            },
+            3 => {
+                let (delim, text) = if rng.gen() { ('"', "abc !?[]^%0") } else { ('^', "abc !?[]\"%0") };
+                let text: Vec<char> = text.chars().collect();
+                code.push(delim);
+                for _ in 0..rng.gen_range(0..4) {
+                    code.push(text[rng.gen_range(0..text.len())]);
+                }
+                code.push(delim);
+            },
+            _ => code.push(lone[rng.gen_range(0..lone.len())]),
+        }
+    }
+    code.extend(open.into_iter().rev());
+    code
+}
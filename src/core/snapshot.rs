@@ -0,0 +1,247 @@
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+
+use super::{ExecError, FnTable, Tape};
+
+const MAGIC: &[u8] = b"bf19";
+// Bumped whenever the layout written by to_bytes changes
+const VERSION: u8 = 3;
+
+// Source position of each char of some code, None where it isn't from the source
+type Sources = Vec<Option<usize>>;
+
+// Everything about a running Interpreter except the io, rng, options and native
+// functions, as plain data. Interpreter::snapshot takes one and
+// Interpreter::resume carries on from it, e.g. in another process after
+// to_bytes and from_bytes
+#[derive(Clone)]
+pub struct Snapshot {
+    pub(crate) source: String,
+    pub(crate) steps: u64,
+    pub(crate) printed: Vec<u8>,
+    pub(crate) fed: Vec<u8>,
+    pub(crate) active_input: usize,
+    pub(crate) expansions: usize,
+    pub(crate) held_cr: bool,
+    pub(crate) reruns: BTreeMap<usize, usize>,
+    pub(crate) tape: Tape,
+    pub(crate) fntable: FnTable,
+    // code, idx, bfmode, nicemode and source positions of each frame,
+    // outermost first. Jumps are worked out again on resume
+    pub(crate) frames: Vec<(Vec<char>, usize, bool, bool, Sources)>,
+    // source positions of each function body
+    pub(crate) bodies: BTreeMap<char, Sources>,
+}
+
+impl Snapshot {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = MAGIC.to_vec();
+        out.push(VERSION);
+        put_str(&mut out, &self.source);
+        put_u64(&mut out, self.steps);
+        put_bytes(&mut out, &self.printed);
+        put_bytes(&mut out, &self.fed);
+        put_u64(&mut out, self.active_input as u64);
+        put_u64(&mut out, self.expansions as u64);
+        out.push(self.held_cr as u8);
+        put_u64(&mut out, self.reruns.len() as u64);
+        for (&depth, &len) in &self.reruns {
+            put_u64(&mut out, depth as u64);
+            put_u64(&mut out, len as u64);
+        }
+
+        let (cells, head) = self.tape.to_vec();
+        out.push(self.tape.initial_cell());
+        put_u64(&mut out, self.tape.head_offset() as u64);
+        put_u64(&mut out, head as u64);
+        put_bytes(&mut out, &cells);
+
+        let (funcs, creating, calls) = self.fntable.parts();
+        put_u64(&mut out, funcs.len() as u64);
+        for (&name, (body, start)) in funcs {
+            put_u64(&mut out, name as u64);
+            put_u64(&mut out, *start as u64);
+            put_chars(&mut out, body);
+            out.push(creating.contains(&name) as u8);
+        }
+        put_u64(&mut out, calls.len() as u64);
+        for (&name, &n) in calls {
+            put_u64(&mut out, name as u64);
+            put_u64(&mut out, n as u64);
+        }
+
+        put_u64(&mut out, self.frames.len() as u64);
+        for (code, idx, bfmode, nicemode, sources) in &self.frames {
+            put_chars(&mut out, code);
+            put_u64(&mut out, *idx as u64);
+            out.push(*bfmode as u8);
+            out.push(*nicemode as u8);
+            put_sources(&mut out, sources);
+        }
+        put_u64(&mut out, self.bodies.len() as u64);
+        for (&name, sources) in &self.bodies {
+            put_u64(&mut out, name as u64);
+            put_sources(&mut out, sources);
+        }
+        out
+    }
+
+    // Inverse of to_bytes. Anything it didn't write, including bytes from a
+    // different version, is ExecError::BadSnapshot
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ExecError> {
+        let mut r = Reader(bytes);
+        if r.take(MAGIC.len())? != MAGIC || r.take(1)? != [VERSION] {
+            return Err(ExecError::BadSnapshot);
+        }
+        let source = r.string()?;
+        let steps = r.u64()?;
+        let printed = r.bytes()?;
+        let fed = r.bytes()?;
+        let active_input = r.usize()?;
+        let expansions = r.usize()?;
+        let held_cr = r.bool()?;
+        let mut reruns = BTreeMap::new();
+        for _ in 0..r.u64()? {
+            reruns.insert(r.usize()?, r.usize()?);
+        }
+
+        let initial_cell = r.take(1)?[0];
+        let pos = r.u64()? as isize;
+        let head = r.usize()?;
+        let cells = r.bytes()?;
+        if head >= cells.len() {
+            return Err(ExecError::BadSnapshot);
+        }
+        let tape = Tape::from_parts(cells, head, pos, initial_cell);
+
+        let mut funcs = BTreeMap::new();
+        let mut creating = BTreeSet::new();
+        for _ in 0..r.u64()? {
+            let name = r.char()?;
+            let start = r.usize()?;
+            funcs.insert(name, (r.chars()?, start));
+            if r.bool()? {
+                creating.insert(name);
+            }
+        }
+        let mut calls = BTreeMap::new();
+        for _ in 0..r.u64()? {
+            calls.insert(r.char()?, r.usize()?);
+        }
+        let fntable = FnTable::from_parts(funcs, creating, calls);
+
+        let len = source.chars().count();
+        let mut frames = Vec::new();
+        for _ in 0..r.u64()? {
+            let (code, idx, bfmode, nicemode) = (r.chars()?, r.usize()?, r.bool()?, r.bool()?);
+            let sources = r.sources(len)?;
+            if sources.len() != code.len() {
+                return Err(ExecError::BadSnapshot);
+            }
+            frames.push((code, idx, bfmode, nicemode, sources));
+        }
+        let mut bodies = BTreeMap::new();
+        for _ in 0..r.u64()? {
+            bodies.insert(r.char()?, r.sources(len)?);
+        }
+        if !r.0.is_empty() {
+            return Err(ExecError::BadSnapshot);
+        }
+        Ok(Self { source, steps, printed, fed, active_input, expansions, held_cr, reruns, tape, fntable, frames, bodies })
+    }
+
+    pub fn tape(&self) -> &Tape { &self.tape }
+
+    pub fn printed(&self) -> &[u8] { &self.printed }
+}
+
+// Everything is written as little-endian u64s, byte strings and UTF-8 strings
+// prefixed with their length, and bools as one byte
+fn put_u64(out: &mut Vec<u8>, n: u64) {
+    out.extend_from_slice(&n.to_le_bytes());
+}
+
+fn put_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    put_u64(out, bytes.len() as u64);
+    out.extend_from_slice(bytes);
+}
+
+fn put_str(out: &mut Vec<u8>, s: &str) {
+    put_bytes(out, s.as_bytes());
+}
+
+fn put_chars(out: &mut Vec<u8>, chars: &[char]) {
+    put_str(out, &chars.iter().collect::<String>());
+}
+
+// Source positions, with u64::MAX standing in for None
+fn put_sources(out: &mut Vec<u8>, sources: &[Option<usize>]) {
+    put_u64(out, sources.len() as u64);
+    for at in sources {
+        put_u64(out, at.map_or(u64::MAX, |at| at as u64));
+    }
+}
+
+// What's left of the bytes being read
+struct Reader<'b>(&'b [u8]);
+
+impl<'b> Reader<'b> {
+    fn take(&mut self, n: usize) -> Result<&'b [u8], ExecError> {
+        if n > self.0.len() {
+            return Err(ExecError::BadSnapshot);
+        }
+        let (taken, rest) = self.0.split_at(n);
+        self.0 = rest;
+        Ok(taken)
+    }
+
+    fn u64(&mut self) -> Result<u64, ExecError> {
+        let mut buf = [0; 8];
+        buf.copy_from_slice(self.take(8)?);
+        Ok(u64::from_le_bytes(buf))
+    }
+
+    fn usize(&mut self) -> Result<usize, ExecError> {
+        usize::try_from(self.u64()?).map_err(|_| ExecError::BadSnapshot)
+    }
+
+    fn bool(&mut self) -> Result<bool, ExecError> {
+        match self.take(1)? {
+            [0] => Ok(false),
+            [1] => Ok(true),
+            _ => Err(ExecError::BadSnapshot),
+        }
+    }
+
+    fn char(&mut self) -> Result<char, ExecError> {
+        u32::try_from(self.u64()?).ok().and_then(char::from_u32).ok_or(ExecError::BadSnapshot)
+    }
+
+    fn bytes(&mut self) -> Result<Vec<u8>, ExecError> {
+        let len = self.usize()?;
+        Ok(self.take(len)?.to_vec())
+    }
+
+    fn string(&mut self) -> Result<String, ExecError> {
+        String::from_utf8(self.bytes()?).map_err(|_| ExecError::BadSnapshot)
+    }
+
+    fn chars(&mut self) -> Result<Vec<char>, ExecError> {
+        Ok(self.string()?.chars().collect())
+    }
+
+    // Positions must be inside a source of len chars
+    fn sources(&mut self, len: usize) -> Result<Sources, ExecError> {
+        let mut sources = Vec::new();
+        for _ in 0..self.u64()? {
+            sources.push(match self.u64()? {
+                u64::MAX => None,
+                at if at < len as u64 => Some(at as usize),
+                _ => return Err(ExecError::BadSnapshot),
+            });
+        }
+        Ok(sources)
+    }
+}
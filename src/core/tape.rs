@@ -0,0 +1,500 @@
+use alloc::vec;
+use alloc::vec::Vec;
+use rand::Rng;
+
+use super::{ExecError, Input};
+
+// The tape
+#[derive(Clone)]
+pub struct Tape {
+    data_l: Vec<u8>, // stack to the left of the head
+    data_r: Vec<u8>, // stack to the right of the head
+    cell: u8, // cell at head
+    initial_cell: u8, // value of cells that haven't been accessed yet
+    pos: isize, // how far right of the cell the head started on it is
+}
+
+impl Default for Tape {
+    fn default() -> Self { Self::new() }
+}
+
+impl Tape {
+    pub fn new() -> Self {
+        Self::with_initial_cell(0)
+    }
+
+    // Tape where cells that haven't been accessed read as initial_cell instead of 0
+    pub fn with_initial_cell(initial_cell: u8) -> Self {
+        Self { data_l: vec![], data_r: vec![], cell: initial_cell, initial_cell, pos: 0 }
+    }
+
+    pub fn initial_cell(&self) -> u8 { self.initial_cell }
+
+    // Get current cell
+    pub fn get(&self) -> u8 { self.cell }
+
+    // Set current cell
+    pub fn set(&mut self, val: u8) { self.cell = val }
+
+    // Get next cell
+    pub fn get_next(&self) -> u8 {
+        *self.data_r.last().unwrap_or(&self.initial_cell)
+    }
+
+    // Set next cell
+    pub fn set_next(&mut self, val: u8) {
+        if !self.data_r.is_empty() {
+            *self.data_r.last_mut().unwrap() = val
+        } else {
+            self.data_r.push(val)
+        }
+    }
+
+    // Get previous cell
+    pub fn get_prev(&self) -> u8 {
+        *self.data_l.last().unwrap_or(&self.initial_cell)
+    }
+
+    // Set previous cell
+    pub fn set_prev(&mut self, val: u8) {
+        if !self.data_l.is_empty() {
+            *self.data_l.last_mut().unwrap() = val
+        } else {
+            self.data_l.push(val)
+        }
+    }
+
+    // Current and next cell, the two that '#', '*', '/' and ':' work on
+    pub fn pair(&self) -> (u8, u8) { (self.get(), self.get_next()) }
+
+    // The current and next cell as a 16-bit number, the current cell being the
+    // high byte
+    pub fn read_u16_be(&self) -> u16 {
+        let (hi, lo) = self.pair();
+        u16::from_be_bytes([hi, lo])
+    }
+    pub fn write_u16_be(&mut self, v: u16) {
+        let [hi, lo] = v.to_be_bytes();
+        self.set(hi);
+        self.set_next(lo);
+    }
+
+    // Swap the current cell with the next one
+    pub fn swap_next(&mut self) {
+        let next = self.get_next();
+        self.set_next(self.cell);
+        self.cell = next;
+    }
+
+    // Rotate the current cell's bits by one. Unlike the shifts '`' and '~' the
+    // bit pushed off one end comes back in at the other, so nothing is lost
+    pub fn rotate_bits_left(&mut self) { self.cell = self.cell.rotate_left(1); }
+    pub fn rotate_bits_right(&mut self) { self.cell = self.cell.rotate_right(1); }
+
+    // Replace the current cell with how many of its bits are 1, from 0 to 8
+    pub fn popcount(&mut self) { self.cell = self.cell.count_ones() as u8; }
+
+    // Move the head right
+    pub fn next(&mut self) {
+        self.pos += 1;
+        self.data_l.push(self.cell);
+        self.cell = self.data_r.pop().unwrap_or(self.initial_cell);
+    }
+
+    // Move the head left
+    pub fn prev(&mut self) {
+        self.pos -= 1;
+        self.data_r.push(self.cell);
+        self.cell = self.data_l.pop().unwrap_or(self.initial_cell);
+    }
+
+    // Treating the tape as rows of width cells, move the head to the cell
+    // above or below, which is just width cells left or right
+    pub fn up(&mut self, width: usize) {
+        (0..width).for_each(|_| self.prev());
+    }
+    pub fn down(&mut self, width: usize) {
+        (0..width).for_each(|_| self.next());
+    }
+
+    // Make room for the head to move n more cells left/right without
+    // reallocating. Moving right pushes onto data_l and moving left onto data_r
+    pub fn reserve_left(&mut self, n: usize) { self.data_r.reserve(n); }
+    pub fn reserve_right(&mut self, n: usize) { self.data_l.reserve(n); }
+
+    // How many more cells the head can move left and right before a reallocation
+    pub fn spare_capacity(&self) -> (usize, usize) {
+        (self.data_r.capacity() - self.data_r.len(), self.data_l.capacity() - self.data_l.len())
+    }
+
+    // Insert a new cell on the left/right side. Everything on that side moves one
+    // further away, including the origin if it's over there
+    pub fn insert_left(&mut self, val: u8) {
+        if self.pos > 0 { self.pos += 1; }
+        self.data_l.push(val);
+    }
+    pub fn insert_right(&mut self, val: u8) {
+        if self.pos < 0 { self.pos -= 1; }
+        self.data_r.push(val);
+    }
+    // Insert a new cell holding initial_cell on the left/right side
+    pub fn insert_left_initial(&mut self) { self.insert_left(self.initial_cell); }
+    pub fn insert_right_initial(&mut self) { self.insert_right(self.initial_cell); }
+    // Delete a cell from the left or right side, the rest of that side moves one
+    // closer. Deleting the origin's cell moves the origin onto the head
+    pub fn delete_left(&mut self) -> u8 {
+        if self.pos > 0 { self.pos -= 1; }
+        self.data_l.pop().unwrap_or(self.initial_cell)
+    }
+    pub fn delete_right(&mut self) -> u8 {
+        if self.pos < 0 { self.pos += 1; }
+        self.data_r.pop().unwrap_or(self.initial_cell)
+    }
+
+    // Number of accessed cells to the left of the head
+    pub fn left_len(&self) -> usize { self.data_l.len() }
+
+    // Lengths of data_l and data_r as they're stored, e.g. to see how much
+    // memory the tape is using
+    pub fn stack_lens(&self) -> (usize, usize) { (self.data_l.len(), self.data_r.len()) }
+
+    // Whether the head is on the leftmost and on the rightmost accessed cell
+    pub fn at_edges(&self) -> (bool, bool) { (self.data_l.is_empty(), self.data_r.is_empty()) }
+
+    // How far right of the origin, the cell it started on, the head is, negative
+    // when it's left of it. This is the head's offset for a debugger. Cells
+    // inserted or deleted between the two move the origin along with its cell
+    // rather than leaving it at a fixed offset, like everything else on the tape
+    pub fn head_offset(&self) -> isize { self.pos }
+
+    // Move the head back to the origin
+    pub fn seek_origin(&mut self) {
+        while self.pos > 0 { self.prev(); }
+        while self.pos < 0 { self.next(); }
+    }
+
+    // Duplicate each cell. The head stays on the first copy of the current cell,
+    // the extra copy goes on top of data_r so it's the head's right neighbor.
+    pub fn expand_2(&mut self) { self.expand_n(2); }
+
+    // Triplicate each cell, head stays on the first copy
+    pub fn expand_3(&mut self) { self.expand_n(3); }
+
+    // Make n copies of each cell, head stays on the first copy. n must be at least 1
+    pub fn expand_n(&mut self, n: usize) {
+        assert!(n > 0, "expand_n(0) would delete every cell");
+        self.data_l = self.data_l.iter().flat_map(|&x| core::iter::repeat_n(x, n)).collect();
+        self.data_r = self.data_r.iter().flat_map(|&x| core::iter::repeat_n(x, n)).collect();
+        self.data_r.extend(core::iter::repeat_n(self.cell, n - 1));
+        self.pos *= n as isize;
+    }
+
+    // expand_2, but fails instead of growing the tape past max_cells or running
+    // out of memory. The tape is left alone if it fails
+    pub fn try_expand_2(&mut self, max_cells: Option<usize>) -> Result<(), ExecError> {
+        self.try_expand(2, max_cells)
+    }
+
+    // expand_3, but fails instead of growing the tape past max_cells or running out of memory
+    pub fn try_expand_3(&mut self, max_cells: Option<usize>) -> Result<(), ExecError> {
+        self.try_expand(3, max_cells)
+    }
+
+    fn try_expand(&mut self, copies: usize, max_cells: Option<usize>) -> Result<(), ExecError> {
+        let cells = self.extent().checked_mul(copies).ok_or(ExecError::TapeTooLarge)?;
+        if max_cells.is_some_and(|max| cells > max) {
+            return Err(ExecError::TapeTooLarge);
+        }
+        let repeat = |stack: &[u8]| -> Result<Vec<u8>, ExecError> {
+            let mut out = Vec::new();
+            out.try_reserve_exact(stack.len() * copies + copies).map_err(|_| ExecError::OutOfMemory)?;
+            out.extend(stack.iter().flat_map(|&x| core::iter::repeat_n(x, copies)));
+            Ok(out)
+        };
+        let data_l = repeat(&self.data_l)?;
+        let mut data_r = repeat(&self.data_r)?;
+        data_r.extend(core::iter::repeat_n(self.cell, copies - 1));
+        self.data_l = data_l;
+        self.data_r = data_r;
+        self.pos *= copies as isize;
+        Ok(())
+    }
+
+    // Keep every other cell, inverse of expand_2. The head stays on its cell and
+    // the cells at odd offsets from it are dropped, so expand_2 then collapse_2
+    // gives back exactly the original tape
+    pub fn collapse_2(&mut self) { self.collapse_n(2); }
+
+    // Keep every nth cell, inverse of expand_n. Like collapse_2 the head stays on
+    // its cell and so do the cells a multiple of n away from it, everything else
+    // is dropped. n must be at least 1, and 1 changes nothing
+    pub fn collapse_n(&mut self, n: usize) {
+        assert!(n > 0, "collapse_n(0) has no cells to keep");
+        let keep = |stack: &[u8]| -> Vec<u8> {
+            stack.iter().rev().skip(n - 1).step_by(n).rev().copied().collect()
+        };
+        self.data_l = keep(&self.data_l);
+        self.data_r = keep(&self.data_r);
+        // an origin on a dropped cell ends up on the next kept one towards the head
+        self.pos /= n as isize;
+    }
+
+    // For each cell that has been accessed, 50% chance of adding a number in -5..=5
+    pub fn randomize<R: Rng + ?Sized>(&mut self, rng: &mut R) {
+        self.data_l = self.data_l.iter().map(|x|
+            if rng.gen() {
+                (*x as i16 + rng.gen_range(-5..=5)) as u8
+            } else {
+                *x
+            }).collect();
+        self.data_r = self.data_r.iter().map(|x|
+            if rng.gen() {
+                (*x as i16 + rng.gen_range(-5..=5)) as u8
+            } else {
+                *x
+            }).collect();
+        if rng.gen() {
+            self.cell = (self.cell as i16 + rng.gen_range(-5..=5)) as u8;
+        }
+    }
+
+    // Make sure n cells to the right of the head have been accessed
+    fn extend_right(&mut self, n: usize) {
+        if let Some(missing) = n.checked_sub(self.data_r.len()) {
+            self.data_r.splice(0..0, core::iter::repeat_n(self.initial_cell, missing));
+        }
+    }
+
+    // Make sure n cells to the left of the head have been accessed
+    fn extend_left(&mut self, n: usize) {
+        if let Some(missing) = n.checked_sub(self.data_l.len()) {
+            self.data_l.splice(0..0, core::iter::repeat_n(self.initial_cell, missing));
+        }
+    }
+
+    // How far right of the head the nearest nonzero cell is, not counting the
+    // head, or None if every accessed cell right of it is 0. Only reads
+    pub fn scan_right_nonzero(&self) -> Option<usize> {
+        self.data_r.iter().rev().position(|&x| x != 0).map(|i| i + 1)
+    }
+
+    // Wrapping sum of the n cells right of the head, not counting the head. Cells
+    // that haven't been accessed count as initial_cell, and stay unaccessed
+    pub fn sum_right(&self, n: usize) -> u8 {
+        let accessed = self.data_r.iter().rev().take(n);
+        let rest = (n - accessed.len()) as u8;
+        accessed.fold(self.initial_cell.wrapping_mul(rest), |sum, &x| sum.wrapping_add(x))
+    }
+
+    // Apply f to n cells starting at the head and going right, accessing any
+    // that haven't been yet. The head doesn't move
+    pub fn map_right(&mut self, n: usize, f: impl Fn(u8) -> u8) {
+        if n == 0 {
+            return;
+        }
+        self.cell = f(self.cell);
+        self.extend_right(n - 1);
+        let len = self.data_r.len();
+        for x in &mut self.data_r[len-(n-1)..] {
+            *x = f(*x);
+        }
+    }
+
+    // Set n cells starting at the head and going right to 0, the way [-]>
+    // repeated would but without moving. Like map_right the cleared cells count
+    // as accessed afterwards, and the stacks aren't trimmed even if they're all
+    // 0 now, so extent() and left_len() don't change under the program
+    pub fn clear_right(&mut self, n: usize) {
+        if n == 0 {
+            return;
+        }
+        self.cell = 0;
+        self.extend_right(n - 1);
+        let len = self.data_r.len();
+        self.data_r[len-(n-1)..].fill(0);
+    }
+
+    // Read input until EOF, putting each byte in the cell right of the head and
+    // moving onto it, the same way a string literal is written. Returns how many
+    // bytes were read, at EOF straight away nothing changes
+    pub fn read_all_from<I: Input + ?Sized>(&mut self, input: &mut I) -> usize {
+        let mut n = 0;
+        while let Some(b) = input.read_byte() {
+            self.next();
+            self.set(b);
+            n += 1;
+        }
+        n
+    }
+
+    // Bitwise complement of every accessed cell. Not the same as '!', which only
+    // swaps 0 and 1 in the head cell
+    pub fn bitwise_not_all(&mut self) {
+        self.cell = !self.cell;
+        for x in self.data_l.iter_mut().chain(&mut self.data_r) {
+            *x = !*x;
+        }
+    }
+
+    // Number of cells that have been accessed
+    pub fn extent(&self) -> usize {
+        self.data_l.len() + 1 + self.data_r.len()
+    }
+
+    // Number of accessed cells that aren't 0
+    pub fn count_nonzero(&self) -> usize {
+        self.fold_accessed(0, |n, _, x| n + (x != 0) as usize)
+    }
+
+    // Cells from the head rightwards up to but not including the first 0, like a
+    // C string. Only reads, the head stays where it is. Stops at the last
+    // accessed cell too, in case initial_cell isn't 0
+    pub fn read_cstring(&self) -> Vec<u8> {
+        core::iter::once(&self.cell)
+            .chain(self.data_r.iter().rev())
+            .take_while(|&&x| x != 0)
+            .copied()
+            .collect()
+    }
+
+    // All accessed cells from left to right, and the index of the head in them
+    pub fn to_vec(&self) -> (Vec<u8>, usize) {
+        let mut cells = self.data_l.clone();
+        cells.push(self.cell);
+        cells.extend(self.data_r.iter().rev());
+        (cells, self.data_l.len())
+    }
+
+    // Every accessed cell as (offset from head, value), left to right, without
+    // building a vec like to_vec does
+    pub fn iter_accessed(&self) -> impl Iterator<Item = (isize, u8)> + '_ {
+        let left = -(self.data_l.len() as isize);
+        let cells = self.data_l.iter().copied()
+            .chain(core::iter::once(self.cell))
+            .chain(self.data_r.iter().rev().copied());
+        (left..).zip(cells)
+    }
+
+    // Fold f over iter_accessed, left to right, e.g. to total up cells weighted
+    // by where they are
+    pub fn fold_accessed<B>(&self, init: B, f: impl Fn(B, isize, u8) -> B) -> B {
+        self.iter_accessed().fold(init, |acc, (offset, x)| f(acc, offset, x))
+    }
+
+    // to_vec with each cell read as two's complement, for programs doing signed arithmetic
+    pub fn to_signed_vec(&self) -> (Vec<i8>, usize) {
+        let (cells, head) = self.to_vec();
+        (cells.into_iter().map(|x| x as i8).collect(), head)
+    }
+
+    // Tape as saved in a Snapshot, head must be a valid index into cells
+    pub(crate) fn from_parts(cells: Vec<u8>, head: usize, pos: isize, initial_cell: u8) -> Self {
+        let mut tape = Self::with_initial_cell(initial_cell);
+        tape.load_vec(cells, head);
+        tape.pos = pos;
+        tape
+    }
+
+    // Inverse of to_vec, head must be a valid index into cells
+    pub(crate) fn load_vec(&mut self, mut cells: Vec<u8>, head: usize) {
+        self.data_r = cells.split_off(head + 1);
+        self.data_r.reverse();
+        self.cell = cells.pop().unwrap();
+        self.data_l = cells;
+    }
+
+    // Rotate all accessed cells right by n, wrapping around the ends. The head
+    // stays where it is, so afterwards it's on the cell that was n to its left
+    pub fn rotate_right(&mut self, n: usize) {
+        let (mut cells, head) = self.to_vec();
+        let len = cells.len();
+        cells.rotate_right(n % len);
+        self.load_vec(cells, head);
+    }
+
+    // Rotate all accessed cells left by n, the head stays where it is
+    pub fn rotate_left(&mut self, n: usize) {
+        let (mut cells, head) = self.to_vec();
+        let len = cells.len();
+        cells.rotate_left(n % len);
+        self.load_vec(cells, head);
+    }
+
+    // Cell at an offset from the head, initial_cell if it hasn't been accessed
+    fn cell_at(&self, offset: isize) -> u8 {
+        let k = offset.unsigned_abs();
+        let stack = match offset {
+            0 => return self.cell,
+            o if o < 0 => &self.data_l,
+            _ => &self.data_r,
+        };
+        stack.len().checked_sub(k).map_or(self.initial_cell, |i| stack[i])
+    }
+
+    // Cell at an offset from the head, accessing it and any between it and the head first
+    fn cell_at_mut(&mut self, offset: isize) -> &mut u8 {
+        let k = offset.unsigned_abs();
+        let stack = match offset {
+            0 => return &mut self.cell,
+            o if o < 0 => {
+                self.extend_left(k);
+                &mut self.data_l
+            },
+            _ => {
+                self.extend_right(k);
+                &mut self.data_r
+            },
+        };
+        let i = stack.len() - k;
+        &mut stack[i]
+    }
+
+    // Write bytes to successive cells starting offset cells from the head, which
+    // can be negative. The head doesn't move
+    pub fn write_at(&mut self, offset: isize, bytes: &[u8]) {
+        for (o, &b) in (offset..).zip(bytes) {
+            *self.cell_at_mut(o) = b;
+        }
+    }
+
+    // Every cell that differs between self and other as (offset from head, old, new),
+    // left to right. The tapes are lined up by their heads
+    pub fn diff(&self, other: &Tape) -> Vec<(isize, u8, u8)> {
+        let left = self.data_l.len().max(other.data_l.len()) as isize;
+        let right = self.data_r.len().max(other.data_r.len()) as isize;
+        (-left..=right)
+            .map(|o| (o, self.cell_at(o), other.cell_at(o)))
+            .filter(|(_, old, new)| old != new)
+            .collect()
+    }
+
+    // Drop cells holding initial_cell from the far ends of the tape. Those read
+    // the same as cells that were never accessed, so this doesn't change what
+    // the tape holds, only how much of it is stored
+    pub fn canonicalize(&mut self) {
+        let initial = self.initial_cell;
+        let keep = |stack: &[u8]| stack.iter().position(|&x| x != initial).unwrap_or(stack.len());
+        self.data_l.drain(..keep(&self.data_l));
+        self.data_r.drain(..keep(&self.data_r));
+    }
+
+    // FNV-1a hash of the canonical tape, so tapes holding the same cells around
+    // the head get the same checksum however much of them has been accessed
+    pub fn checksum(&self) -> u64 {
+        let initial = self.initial_cell;
+        let trim = |stack: &'_ [u8]| -> usize { stack.iter().position(|&x| x != initial).unwrap_or(stack.len()) };
+        let left = &self.data_l[trim(&self.data_l)..];
+        let right = &self.data_r[trim(&self.data_r)..];
+        let mut hash: u64 = 0xcbf29ce484222325;
+        let mut feed = |b: u8| {
+            hash ^= b as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        };
+        feed(initial);
+        (left.len() as u64).to_le_bytes().iter().for_each(|&b| feed(b));
+        left.iter().for_each(|&b| feed(b));
+        feed(self.cell);
+        right.iter().rev().for_each(|&b| feed(b));
+        hash
+    }
+}
@@ -0,0 +1,95 @@
+use alloc::vec::Vec;
+
+use super::Tape;
+
+// One command the interpreter ran, as passed to Options::record
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceEntry {
+    pub command: char,
+    // position of the command in the code it was running
+    pub idx: usize,
+    // What the command put on the tape that can't be worked out from the tape
+    // alone: the byte ',' read, 'P' peeked, 'C' counted, 'B' set or '5' rounded
+    // to (which depends on Options::round_to_next), the bytes of a string or from
+    // 'R', the value '?' picked, 1 or 0 for '$' going right or left (2 if
+    // Options::random_walk_reflect kept it still), every cell after '4', and
+    // Options::grid_width as 8 little-endian bytes for 'U' and 'V' (none if it
+    // wasn't set)
+    pub bytes: Vec<u8>,
+}
+
+// Apply the tape effects of a recorded run to tape, which should start out the
+// same as the run's did. Nothing is re-run: jumps, calls and output are already
+// baked into the order of the trace, and the random commands ('?', '$' and '4')
+// replay the values recorded rather than rolling new ones, so a replay always
+// ends up where the recorded run did but can't be used to try other outcomes.
+// Keep this in step with the commands in Interpreter::step
+pub fn replay(trace: &[TraceEntry], tape: &mut Tape) {
+    for entry in trace {
+        match entry.command {
+            '>' => tape.next(),
+            '<' => tape.prev(),
+            '{' => {tape.delete_left();},
+            '}' => {tape.delete_right();},
+            '(' => tape.insert_left_initial(),
+            ')' => tape.insert_right_initial(),
+            '+' => tape.set(tape.get().wrapping_add(1)),
+            '-' => tape.set(tape.get().wrapping_sub(1)),
+            '*' => tape.set(tape.get().wrapping_mul(tape.get_next())),
+            '/' => tape.set(tape.get().checked_div(tape.get_next()).unwrap_or(255)),
+            '!' => match tape.get() {
+                0 => tape.set(1),
+                1 => tape.set(0),
+                _ => ()
+            },
+            ',' | '?' | 'P' | 'C' | '5' | 'B' => tape.set(entry.bytes[0]),
+            '$' => match entry.bytes[0] {
+                0 => tape.prev(),
+                1 => tape.next(),
+                _ => (),
+            },
+            '"' | 'R' => for &b in &entry.bytes {
+                tape.next();
+                tape.set(b);
+            },
+            '`' => tape.set(tape.get() << 1),
+            '~' => tape.set(tape.get() >> 1),
+            ':' => tape.set_next(tape.get()),
+            '2' => tape.expand_2(),
+            '3' => tape.expand_3(),
+            '4' => {
+                let (_, head) = tape.to_vec();
+                tape.load_vec(entry.bytes.clone(), head);
+            },
+            '8' => {
+                tape.prev();
+                tape.prev();
+                tape.prev();
+                for _ in 0..7 {
+                    tape.set(8);
+                    tape.next();
+                }
+            },
+            'S' => tape.swap_next(),
+            'D' => tape.set_prev(tape.get()),
+            'N' => tape.bitwise_not_all(),
+            'O' => tape.seek_origin(),
+            'L' => tape.set(tape.left_len().min(255) as u8),
+            'A' => tape.set(tape.sum_right(tape.get() as usize)),
+            'Q' => tape.rotate_bits_left(),
+            'W' => tape.rotate_bits_right(),
+            'X' => tape.popcount(),
+            'U' | 'V' if entry.bytes.len() == 8 => {
+                let mut width = [0; 8];
+                width.copy_from_slice(&entry.bytes);
+                let width = u64::from_le_bytes(width) as usize;
+                if entry.command == 'U' {
+                    tape.up(width);
+                } else {
+                    tape.down(width);
+                }
+            },
+            _ => (),
+        }
+    }
+}
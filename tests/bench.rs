@@ -0,0 +1,83 @@
+// Rough timings, run with `cargo test --release --test bench -- --ignored --nocapture`
+use bf19::{Options, Program};
+use std::time::Instant;
+
+const LOOPS: &str = "++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]>>.>---.+++++++..+++.>>.<-.<.+++.------.--------.>>+.";
+
+#[test]
+fn jump_table_matches_index_table() {
+    let program = Program::compile(LOOPS, &Options::default()).unwrap();
+    for i in 0..LOOPS.len() {
+        let expected = program.index_table().get_by_left(&i).or_else(|| program.index_table().get_by_right(&i));
+        assert_eq!(program.jump(i), expected.copied());
+    }
+}
+
+#[test]
+#[ignore]
+fn bench_jump_lookup() {
+    let code = LOOPS.repeat(100);
+    let program = Program::compile(&code, &Options::default()).unwrap();
+    let brackets: Vec<usize> = code.char_indices().filter(|(_, c)| "[]".contains(*c)).map(|(i, _)| i).collect();
+    let rounds = 1000;
+
+    let start = Instant::now();
+    let mut bimap_sum = 0;
+    for _ in 0..rounds {
+        for &i in &brackets {
+            let table = program.index_table();
+            bimap_sum += table.get_by_left(&i).or_else(|| table.get_by_right(&i)).unwrap();
+        }
+    }
+    let bimap_time = start.elapsed();
+
+    let start = Instant::now();
+    let mut flat_sum = 0;
+    for _ in 0..rounds {
+        for &i in &brackets {
+            flat_sum += program.jump(i).unwrap();
+        }
+    }
+    let flat_time = start.elapsed();
+
+    assert_eq!(bimap_sum, flat_sum);
+    println!("{} lookups: BiMap {:?}, jump table {:?}", rounds * brackets.len(), bimap_time, flat_time);
+}
+
+// Mostly clears and moves, which Program::compile picks out and runs in one go
+const IDIOMS: &str = "-[->+<]>[-<+>]<+++[->+<]>[-]<[-].>[-<+>]<.";
+
+// The same code with a space in each idiom, so it runs a command at a time
+fn naive(code: &str) -> String {
+    code.replace("[-", "[ -")
+}
+
+fn run(code: &str) -> Vec<u8> {
+    let mut output = vec![];
+    let program = Program::compile(code, &Options::default()).unwrap();
+    program.run(Options::default(), &mut &b""[..], &mut output, &mut rand::thread_rng()).unwrap();
+    output
+}
+
+#[test]
+fn idioms_match_naive() {
+    for code in [IDIOMS, "+++[->+<]<[-<+>]>>.<.<.", "++[-]>[->+<]<[-<+>]>>.", "\"[-]\"."] {
+        assert_eq!(run(code), run(&naive(code)), "{}", code);
+    }
+}
+
+#[test]
+#[ignore]
+fn bench_idioms() {
+    let code = IDIOMS.repeat(1000);
+    let start = Instant::now();
+    let fast = run(&code);
+    let fast_time = start.elapsed();
+
+    let start = Instant::now();
+    let slow = run(&naive(&code));
+    let slow_time = start.elapsed();
+
+    assert_eq!(fast, slow);
+    println!("{} bytes of idioms: shortcut {:?}, naive {:?}", code.len(), fast_time, slow_time);
+}
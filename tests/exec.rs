@@ -0,0 +1,778 @@
+use bf19::{
+    exec_timeline, exec_with, exec_with_funcs_ret, source_location, Cycling, ExecError, FnTable, LineBuffered,
+    NewlineMode, Options, Output, OutputBuffering, Severity, StepStats, Warning,
+};
+use rand::{rngs::StdRng, SeedableRng};
+
+// Run code with the given options and input, returning everything it printed
+fn run_opts(code: &str, options: Options, mut input: &[u8]) -> Result<Vec<u8>, ExecError> {
+    let mut output = vec![];
+    exec_with(code, options, &mut input, &mut output, &mut StdRng::seed_from_u64(19))?;
+    Ok(output)
+}
+
+fn run(code: &str, input: &[u8]) -> Result<Vec<u8>, ExecError> {
+    run_opts(code, Options::default(), input)
+}
+
+fn extended() -> Options<'static> {
+    Options { extensions: true, ..Options::default() }
+}
+
+#[test]
+fn injected_io() {
+    assert_eq!(run(",+.,+.", b"ab").unwrap(), b"bc");
+    assert_eq!(run(",.", b"").unwrap(), b"\0");
+}
+
+#[test]
+fn swap_command() {
+    assert_eq!(run_opts(",>,<S.>.", extended(), b"ab").unwrap(), b"ba");
+    // without extensions 'S' is just a function name
+    assert_eq!(run(",>,<S.>.S", b"ab").unwrap(), b"");
+}
+
+#[test]
+fn break_skips_quoted_brackets() {
+    // '\' leaves the loop at its real ']', not the ones in the comment or string
+    assert_eq!(run("+[\\^]^\"]\"-]+.", b"").unwrap(), [2]);
+    assert_eq!(run("+[\\\"]\"^]^-]+.", b"").unwrap(), [2]);
+    // and with nothing in the way
+    assert_eq!(run("+[\\-]+.", b"").unwrap(), [2]);
+}
+
+#[test]
+fn skip_at_the_end() {
+    // '#' skipping from the last char goes past the end, which still just ends
+    assert_eq!(run("+.#", b"").unwrap(), [1]);
+    assert_eq!(run(".#", b"").unwrap(), [0]);
+    // the same at the end of a function, after which the caller carries on
+    assert_eq!(run("F+#F F.", b"").unwrap(), [1]);
+    assert_eq!(run("F#F F+.", b"").unwrap(), [1]);
+    // skipping the start of a string or comment runs what's inside, and its end
+    // does nothing rather than going back into it
+    assert_eq!(run("+#\"++\".", b"").unwrap(), [3]);
+    assert_eq!(run("+#^+^.", b"").unwrap(), [2]);
+    // with the tests' seed the '&' skips too
+    assert_eq!(run("+&^+^.", b"").unwrap(), [2]);
+    // and of rerun output, here "+#" run with the head on the '#'
+    assert_eq!(run("\"+#\"<.>.'.", b"").unwrap(), b"+#$");
+}
+
+#[test]
+fn recursive_function() {
+    let options = || Options { recursive_functions: true, ..Options::default() };
+    // F prints and decrements the cell, then calls itself until it hits 0
+    assert_eq!(run_opts("+++F[-.FF]F F", options(), b"").unwrap(), [2, 1, 0]);
+    // the same function with a bracket after the recursive call
+    assert_eq!(run_opts("++F[-FF.]F F", options(), b"").unwrap(), [0, 0]);
+}
+
+#[test]
+fn function_name_ends_definition() {
+    // without recursion the second F ends the body, the third calls it
+    assert_eq!(run("+++F.-F FF", b"").unwrap(), [3, 2]);
+}
+
+#[test]
+fn trace_to_sink() {
+    let mut trace = vec![];
+    let options = Options { trace: Some(Box::new(&mut trace)), ..Options::default() };
+    assert_eq!(run_opts("+\n.", options, b"").unwrap(), [1]);
+    assert_eq!(
+        String::from_utf8(trace).unwrap(),
+        "::DEBUG:: running '+' (idx 0, line 1, col 1)\n\
+         ::DEBUG:: running '\\n' (idx 1, line 1, col 2)\n\
+         ::DEBUG:: running '.' (idx 2, line 2, col 1)\n"
+    );
+}
+
+#[test]
+fn locations() {
+    let code = "+\n\n.é[\n]";
+    let at = |index| source_location(code.chars(), index);
+    assert_eq!(at(0), (1, 1));
+    assert_eq!(at(1), (1, 2));
+    assert_eq!(at(3), (3, 1));
+    // counted in chars, not bytes
+    assert_eq!(at(5), (3, 3));
+    assert_eq!(at(7), (4, 1));
+    assert_eq!(at(100), (4, 2));
+}
+
+#[test]
+fn strict_ascii() {
+    let strict = || Options { strict_ascii: true, ..Options::default() };
+    assert_eq!(run_opts("+é.", strict(), b""), Err(ExecError::NonAscii { index: 1 }));
+    // fine inside strings and comments
+    assert_eq!(run_opts("^é^\"é\"", strict(), b"").unwrap(), b"");
+    // and without the option é is just a function name
+    assert_eq!(run("+é.", b"").unwrap(), b"");
+}
+
+#[test]
+fn metrics_callback() {
+    let mut calls = vec![];
+    let options = Options {
+        metrics: Some(Box::new(|stats| calls.push(stats))),
+        metrics_every: 3,
+        ..Options::default()
+    };
+    // the last call happens before the final '+', there's no call after it
+    run_opts("+.>+.>+.>+", options, b"").unwrap();
+    assert_eq!(calls, [
+        StepStats { steps: 3, output_bytes: 1, tape_extent: 2 },
+        StepStats { steps: 6, output_bytes: 2, tape_extent: 3 },
+        StepStats { steps: 9, output_bytes: 3, tape_extent: 4 },
+    ]);
+}
+
+#[test]
+fn observe_callback() {
+    let code = "+>>< +[-]";
+    let mut seen = vec![];
+    let options = Options {
+        observe: Some(Box::new(|tape, idx| seen.push((idx, tape.head_offset(), tape.get())))),
+        observe_every: 1,
+        ..Options::default()
+    };
+    run_opts(code, options, b"").unwrap();
+    assert_eq!(seen, [(0, 0, 1), (1, 1, 0), (2, 2, 0), (3, 1, 0), (4, 1, 0), (5, 1, 1), (6, 1, 1), (7, 1, 0), (8, 1, 0)]);
+
+    let mut seen = vec![];
+    let options = Options {
+        observe: Some(Box::new(|_, idx| seen.push(idx))),
+        observe_every: 4,
+        ..Options::default()
+    };
+    run_opts(code, options, b"").unwrap();
+    assert_eq!(seen, [3, 7]);
+}
+
+#[test]
+fn random_walk_reflect() {
+    let walk = |reflect: bool, code: &str| {
+        let mut seen = vec![];
+        let options = Options {
+            random_walk_reflect: reflect,
+            observe: Some(Box::new(|tape, _| seen.push(tape.head_offset()))),
+            observe_every: 1,
+            ..Options::default()
+        };
+        run_opts(code, options, b"").unwrap();
+        seen
+    };
+    // cells 0 and 1 accessed, then 40 random steps
+    let code = format!("><{}", "$".repeat(40));
+    let reflected = walk(true, &code);
+    assert!(reflected.iter().all(|p| (0..=1).contains(p)), "{:?}", reflected);
+    assert!(reflected[2..].contains(&1));
+    assert!(walk(false, &code).iter().any(|p| !(0..=1).contains(p)));
+    // with only the one cell there's nowhere to go
+    assert!(walk(true, "$$$$").iter().all(|&p| p == 0));
+}
+
+#[test]
+fn modes_disabled() {
+    let no_modes = || Options { allow_modes: false, ..Options::default() };
+    // bfmode would normally skip the ':'
+    assert_eq!(run_opts("+_:>.", no_modes(), b"").unwrap(), [1]);
+    assert_eq!(run("+_:>.", b"").unwrap(), [0]);
+    assert_eq!(run_opts("+6+.9", no_modes(), b"").unwrap(), [2]);
+    assert_eq!(run("+6+.9+.", b"").unwrap(), b"Nice.\nNice.\n\x02");
+}
+
+#[test]
+fn rerun_output_with_brackets() {
+    // prints "[-]" then runs it, which has its own jumps
+    assert_eq!(run("\"[-]\"<<.>.>.'.", b"").unwrap(), b"[-]\0");
+}
+
+// Print n cells to the left of the head, ending back where it started
+fn print_string(code: &str, n: usize) -> String {
+    format!("{}{}{}", code, "<".repeat(n - 1), ".>".repeat(n))
+}
+
+#[test]
+fn string_escapes() {
+    let escapes = || Options { string_escapes: true, ..Options::default() };
+    assert_eq!(run_opts(&print_string(r#""\x41\x7f""#, 2), escapes(), b"").unwrap(), b"A\x7f");
+    assert_eq!(run_opts(&print_string(r#""\n\t""#, 2), escapes(), b"").unwrap(), b"\n\t");
+    assert_eq!(run_opts(&print_string(r#""\\""#, 1), escapes(), b"").unwrap(), b"\\");
+    // an escaped quote doesn't end the string
+    assert_eq!(run_opts(&print_string(r#""a\"b""#, 3), escapes(), b"").unwrap(), b"a\"b");
+    assert_eq!(run_opts(r#"+"\q""#, escapes(), b""), Err(ExecError::InvalidEscape { index: 2 }));
+    assert_eq!(run_opts(r#""ab\x4""#, escapes(), b""), Err(ExecError::InvalidEscape { index: 3 }));
+    // without the option backslashes are kept as is
+    assert_eq!(run(&print_string(r#""\n""#, 2), b"").unwrap(), b"\\n");
+}
+
+#[test]
+fn output_filter() {
+    let upper = || Options {
+        output_filter: Some(Box::new(|b: u8| Some(b.to_ascii_uppercase()))),
+        ..Options::default()
+    };
+    assert_eq!(run_opts(&print_string("\"aB1z\"", 4), upper(), b"").unwrap(), b"AB1Z");
+    let drop_controls = Options {
+        output_filter: Some(Box::new(|b: u8| Some(b).filter(|b| !b.is_ascii_control()))),
+        ..Options::default()
+    };
+    assert_eq!(run_opts(",.,.,.", drop_controls, b"a\x07b").unwrap(), b"ab");
+}
+
+#[test]
+fn misplaced_copy() {
+    assert_eq!(run("=", b""), Err(ExecError::MisplacedCopy { index: 0 }));
+    assert_eq!(run("+.=", b""), Err(ExecError::MisplacedCopy { index: 2 }));
+    // 'S' is a command with extensions on, and skipping it lands on the '='
+    assert_eq!(run_opts("+#S=X", extended(), b""), Err(ExecError::MisplacedCopy { index: 3 }));
+}
+
+#[test]
+fn copy_from_undefined_function() {
+    // silent by default
+    assert_eq!(run("A=B+.", b"").unwrap(), [1]);
+
+    let mut warnings = vec![];
+    let options = Options {
+        undefined_copy: Severity::Warn,
+        warnings: Some(Box::new(|w| warnings.push(w))),
+        ..Options::default()
+    };
+    assert_eq!(run_opts("A=B+.", options, b"").unwrap(), [1]);
+    assert_eq!(warnings, [Warning::CopyUndefined { from: 'B', to: 'A' }]);
+
+    // copying a defined function is fine
+    let options = Options { undefined_copy: Severity::Error, ..Options::default() };
+    assert_eq!(run_opts("B+B A=B A.", options, b"").unwrap(), [1]);
+    let options = Options { undefined_copy: Severity::Error, ..Options::default() };
+    let error = run_opts("A=B+.", options, b"").unwrap_err();
+    assert_eq!(error, ExecError::Warning(Warning::CopyUndefined { from: 'B', to: 'A' }));
+    assert_eq!(error.to_string(), "copied from an undefined function");
+}
+
+#[test]
+fn initial_cell() {
+    let options = Options { initial_cell: 255, ..Options::default() };
+    assert_eq!(run_opts(".>.)>.", options, b"").unwrap(), [255, 255, 255]);
+}
+
+#[test]
+fn return_at_top_level() {
+    assert_eq!(run("+.@+.", b"").unwrap(), [1]);
+}
+
+#[test]
+fn return_from_function() {
+    // '@' leaves F early, then the caller carries on
+    assert_eq!(run("+F.@+.F F+.", b"").unwrap(), [1, 2]);
+}
+
+#[test]
+fn halt_from_function() {
+    assert_eq!(run_opts("+F.H+.F F+.", extended(), b"").unwrap(), [1]);
+    assert_eq!(run_opts("+.H+.", extended(), b"").unwrap(), [1]);
+}
+
+#[test]
+fn read_all_command() {
+    // reverse the input by reading it all and printing back to the start
+    assert_eq!(run_opts("R[.<]", extended(), b"hello").unwrap(), b"olleh");
+    assert_eq!(run_opts("+R.", extended(), b"").unwrap(), [1]);
+}
+
+#[test]
+fn functions_persist_between_calls() {
+    let (output, fntable) = exec_with_funcs_ret("+++F.-F", FnTable::default()).unwrap();
+    assert_eq!(output, b"");
+    assert!(fntable.exists('F'));
+    // a fresh tape, but F is still defined
+    let (output, fntable) = exec_with_funcs_ret("++FF", fntable).unwrap();
+    assert_eq!(output, [2, 1]);
+    assert!(fntable.exists('F'));
+}
+
+#[test]
+fn count_functions() {
+    let (_, fntable) = exec_with_funcs_ret("A+A B-B C.C", FnTable::new()).unwrap();
+    assert_eq!(fntable.len(), 3);
+    // D is still open when the program ends
+    let (_, fntable) = exec_with_funcs_ret("A+A D-", FnTable::new()).unwrap();
+    assert_eq!(fntable.len(), 1);
+    assert!(FnTable::new().is_empty());
+}
+
+#[test]
+fn uncalled_functions() {
+    let (_, fntable) = exec_with_funcs_ret("A+A B-B C.C AC", FnTable::new()).unwrap();
+    assert_eq!(fntable.uncalled(), ['B']);
+    // calls made by an earlier run still count
+    let (_, fntable) = exec_with_funcs_ret("B", fntable).unwrap();
+    assert!(fntable.uncalled().is_empty());
+}
+
+#[test]
+fn unmatched_pairs() {
+    assert_eq!(run("+%-%%.", b""), Err(ExecError::UnmatchedPair { symbol: '%', index: 4 }));
+    assert_eq!(run("0+0.0", b""), Err(ExecError::UnmatchedPair { symbol: '0', index: 4 }));
+    // inside strings and comments they don't count
+    assert_eq!(run("%\"%\"^%^%.", b"").unwrap(), [0]);
+    assert_eq!(run("0+0\"0\".", b"").unwrap(), b"0");
+    // paired at the top level, but F's body alone isn't, which shows up when it's called
+    assert_eq!(run("F%F F%", b""), Err(ExecError::UnmatchedPair { symbol: '%', index: 0 }));
+}
+
+#[test]
+fn ignored_chars() {
+    // \r is ignored by default, so CRLF source doesn't define a function
+    assert_eq!(run("+.\r\n+.\r\n", b"").unwrap(), [1, 2]);
+    let options = Options { ignored_chars: " x", ..Options::default() };
+    assert_eq!(run_opts("+x.x +.", options, b"").unwrap(), [1, 2]);
+    assert_eq!(run("+\n.\n", b"").unwrap(), [1]);
+    // with \n no longer ignored it's a function name, so this just defines it
+    let options = Options { ignored_chars: "", ..Options::default() };
+    assert_eq!(run_opts("+\n.\n", options, b"").unwrap(), b"");
+}
+
+#[test]
+fn peek_output_command() {
+    // nothing printed yet reads 0
+    assert_eq!(run_opts("+P.", extended(), b"").unwrap(), [0]);
+    // echo the input, then print it again only if it was an 'a'
+    let branch = "Y.Y,.>P\"a\"<#Y";
+    assert_eq!(run_opts(branch, extended(), b"a").unwrap(), b"aa");
+    assert_eq!(run_opts(branch, extended(), b"b").unwrap(), b"b");
+}
+
+#[test]
+fn max_expansions() {
+    let options = || Options { max_expansions: Some(5), ..Options::default() };
+    assert_eq!(run_opts("+++++[2-]+.", options(), b"").unwrap(), [1]);
+    assert_eq!(run_opts("++++++[2-]+.", options(), b""), Err(ExecError::TooManyExpansions));
+    // '2' and '3' share the count
+    assert_eq!(run_opts("23232", options(), b"").map(|_| ()), Ok(()));
+    assert_eq!(run_opts("232323", options(), b""), Err(ExecError::TooManyExpansions));
+}
+
+#[test]
+fn max_cells() {
+    let options = || Options { max_cells: Some(10), ..Options::default() };
+    // 4 cells, then 8, then the next would be 16
+    assert_eq!(run_opts("+>+>+>+2.", options(), b"").unwrap(), [1]);
+    assert_eq!(run_opts("+>+>+>+22.", options(), b""), Err(ExecError::TapeTooLarge));
+    assert_eq!(run_opts("+>+>+>+3.", options(), b""), Err(ExecError::TapeTooLarge));
+}
+
+#[test]
+fn copy_to_next() {
+    assert_eq!(run("+++:>.", b"").unwrap(), [3]);
+    assert_eq!(run(">+++<++>:>.<.", b"").unwrap(), [3, 3]);
+}
+
+#[test]
+fn copy_to_prev() {
+    assert_eq!(run_opts("+++D<.", extended(), b"").unwrap(), [3]);
+    assert_eq!(run_opts("<+++>++<D<.>.", extended(), b"").unwrap(), [3, 3]);
+}
+
+#[test]
+fn call_callback() {
+    let mut calls = vec![];
+    let options = Options {
+        recursive_functions: true,
+        on_call: Some(Box::new(|name| calls.push(name))),
+        ..Options::default()
+    };
+    // F counts down from 3 calling itself, G is called once on its own
+    assert_eq!(run_opts("+++F[-.FF]F G+G F G", options, b"").unwrap(), [2, 1, 0]);
+    assert_eq!(calls, ['F', 'F', 'F', 'F', 'G']);
+}
+
+#[test]
+fn round_to_5() {
+    let round = |n: u8| run(",5.", &[n]).unwrap()[0];
+    let top: Vec<u8> = (250..=255).map(round).collect();
+    assert_eq!(top, [250, 250, 250, 255, 255, 255]);
+    for n in 0..=255 {
+        let r = round(n);
+        assert_eq!(r % 5, 0);
+        assert!(r.abs_diff(n) <= 2, "{} rounded to {}", n, r);
+    }
+}
+
+#[test]
+fn switch_input() {
+    let options = || Options {
+        extensions: true,
+        inputs: vec![Box::new(&b"xy"[..]), Box::new(&b"z"[..])],
+        ..Options::default()
+    };
+    // main input, then inputs[0], inputs[1], back to inputs[0] and to the main one
+    let code = ",.>+I>,.>++I>,.<<<I>,.>>>I,.";
+    assert_eq!(run_opts(code, options(), b"ab").unwrap(), b"axzyb");
+    // 'R' reads whichever one is active
+    assert_eq!(run_opts(">+I<R[.<]", options(), b"ab").unwrap(), b"yx");
+    assert_eq!(run_opts("+++I", options(), b""), Err(ExecError::NoSuchInput));
+}
+
+#[test]
+fn cycling_input() {
+    let mut output = vec![];
+    let mut input = Cycling::new(b"ab".to_vec());
+    exec_with(",.,.,.,.,.", Options::default(), &mut input, &mut output, &mut StdRng::seed_from_u64(19)).unwrap();
+    assert_eq!(output, b"ababa");
+    // nothing to cycle through is just EOF
+    let mut output = vec![];
+    exec_with("+,.", Options::default(), &mut Cycling::new(vec![]), &mut output, &mut StdRng::seed_from_u64(19)).unwrap();
+    assert_eq!(output, [0]);
+}
+
+#[test]
+fn line_buffered_input() {
+    // each ,[.,] reads one line and stops at the 0 standing in for its ending
+    let mut output = vec![];
+    let mut input = LineBuffered::new(&b"ab\ncd\r\n\nrest"[..]);
+    let code = ",[.,]+.,[.,]+.,[.,]+.,[.,]+.,.";
+    exec_with(code, Options::default(), &mut input, &mut output, &mut StdRng::seed_from_u64(19)).unwrap();
+    assert_eq!(output, b"ab\x01cd\x01\x01rest\x01\0");
+}
+
+#[test]
+fn output_timeline() {
+    let timeline = |code, max_len| {
+        let mut output = vec![];
+        let rng = &mut StdRng::seed_from_u64(19);
+        exec_timeline(code, Options::default(), &mut &b""[..], &mut output, rng, max_len).unwrap()
+    };
+    assert_eq!(timeline("+.+.+.", 100), [(2, 1), (4, 2), (6, 3)]);
+    let countdown = timeline("++++[.-]", 100);
+    assert_eq!(countdown.iter().map(|&(_, b)| b).collect::<Vec<_>>(), [4, 3, 2, 1]);
+    assert!(countdown.windows(2).all(|w| w[0].0 < w[1].0));
+    // only the first max_len are kept, the rest still run
+    assert_eq!(timeline("+.+.+.", 2), [(2, 1), (4, 2)]);
+}
+
+#[test]
+fn newline_mode() {
+    let mode = |newline_mode| Options { newline_mode, ..Options::default() };
+    let code = "\"a\r\n\"<<.>.>.>+.";
+    assert_eq!(run_opts(code, mode(NewlineMode::Unchanged), b"").unwrap(), b"a\r\n\x01");
+    // a "\r\n" is already what Crlf wants
+    assert_eq!(run_opts(code, mode(NewlineMode::Crlf), b"").unwrap(), b"a\r\n\x01");
+    assert_eq!(run_opts(code, mode(NewlineMode::Lf), b"").unwrap(), b"a\n\x01");
+
+    // only the pairs are translated, lone '\r's and '\n's stay
+    let code = print_string("\"\r\na\nb\r\rc\r\n\r\"", 10);
+    assert_eq!(run_opts(&code, mode(NewlineMode::Crlf), b"").unwrap(), b"\r\na\r\nb\r\rc\r\n\r");
+    assert_eq!(run_opts(&code, mode(NewlineMode::Lf), b"").unwrap(), b"\na\nb\r\rc\n\r");
+    assert_eq!(run_opts(&format!("{}.", "+".repeat(13)), mode(NewlineMode::Lf), b"").unwrap(), b"\r");
+}
+
+// Output that remembers how much had been written at each flush
+#[derive(Default)]
+struct FlushLog {
+    bytes: Vec<u8>,
+    flushes: Vec<usize>,
+}
+
+impl Output for FlushLog {
+    fn write_bytes(&mut self, bytes: &[u8]) {
+        self.bytes.extend_from_slice(bytes);
+    }
+    fn flush(&mut self) {
+        self.flushes.push(self.bytes.len());
+    }
+}
+
+fn flushes(buffering: OutputBuffering) -> Vec<usize> {
+    let code = print_string(r#""line1\nline2""#, 11);
+    let options = Options { string_escapes: true, output_buffering: buffering, ..Options::default() };
+    let mut output = FlushLog::default();
+    exec_with(&code, options, &mut &b""[..], &mut output, &mut StdRng::seed_from_u64(19)).unwrap();
+    assert_eq!(output.bytes, b"line1\nline2");
+    output.flushes
+}
+
+// Output that keeps each write separate
+#[derive(Default)]
+struct WriteLog(Vec<Vec<u8>>);
+
+impl Output for WriteLog {
+    fn write_bytes(&mut self, bytes: &[u8]) {
+        self.0.push(bytes.to_vec());
+    }
+}
+
+#[test]
+fn print_large_source() {
+    let code = format!("++[;-]^{}^", "bf19".repeat(10_000));
+    let mut output = WriteLog::default();
+    exec_with(&code, Options::default(), &mut &b""[..], &mut output, &mut StdRng::seed_from_u64(19)).unwrap();
+    assert_eq!(output.0, [code.as_bytes(), code.as_bytes()]);
+}
+
+#[test]
+fn output_buffering() {
+    assert_eq!(flushes(OutputBuffering::Unbuffered), (1..=11).chain([11]).collect::<Vec<_>>());
+    // after the newline and once more at the end
+    assert_eq!(flushes(OutputBuffering::LineBuffered), [6, 11]);
+    assert_eq!(flushes(OutputBuffering::FullyBuffered), [11]);
+}
+
+#[test]
+fn flush_after_error() {
+    // nothing is flushed until the program stops, even if it stops by failing
+    let options = Options { output_buffering: OutputBuffering::FullyBuffered, ..Options::default() };
+    let mut output = FlushLog::default();
+    let res = exec_with("+.+.+.1+.", options, &mut &b""[..], &mut output, &mut StdRng::seed_from_u64(19));
+    assert_eq!(res, Err(ExecError::Unimplemented { command: '1', index: 6 }));
+    assert_eq!(output.bytes, [1, 2, 3]);
+    assert_eq!(output.flushes, [3]);
+}
+
+#[test]
+fn bitwise_not_command() {
+    assert_eq!(run_opts("+>>-<N.>.<<.", extended(), b"").unwrap(), [255, 0, 254]);
+    // '!' on the same cell is a boolean not
+    assert_eq!(run_opts("+!.", extended(), b"").unwrap(), [0]);
+}
+
+#[test]
+fn bounded_random() {
+    let bounded = || Options { bounded_random: true, ..Options::default() };
+    // 50 rolls of 0..=6, starting from 6 each time
+    let dice = &format!("{}[>++++++?.[-]<-]", "+".repeat(50));
+    let rolls = run_opts(dice, bounded(), b"").unwrap();
+    assert_eq!(rolls.len(), 50);
+    assert!(rolls.iter().all(|&r| r <= 6));
+    assert!((0..=6).all(|n| rolls.contains(&n)));
+    // the same seed gives the same rolls
+    assert_eq!(run_opts(dice, bounded(), b"").unwrap(), rolls);
+    // a 0 cell can only give 0
+    assert_eq!(run_opts("?.", bounded(), b"").unwrap(), [0]);
+    assert!(run(dice, b"").unwrap().iter().any(|&r| r > 6));
+}
+
+#[test]
+fn empty_programs() {
+    for code in ["", " ", " \n\t\r", "^comment^", "^ [ % 0 \" ^", "^^"] {
+        assert_eq!(run(code, b"").unwrap(), b"", "{:?}", code);
+        assert_eq!(run_opts(code, extended(), b"").unwrap(), b"", "{:?}", code);
+    }
+    // an empty function is fine to call too
+    assert_eq!(run("FF F", b"").unwrap(), b"");
+}
+
+#[test]
+fn seek_origin_command() {
+    assert_eq!(run_opts("+++>>>>+<<O.", extended(), b"").unwrap(), [3]);
+    assert_eq!(run_opts("+++<<<O.", extended(), b"").unwrap(), [3]);
+}
+
+#[test]
+fn trap_command() {
+    let mut traps = vec![];
+    let options = Options {
+        extensions: true,
+        on_trap: Some(Box::new(|tape, idx| traps.push((idx, tape.head_offset(), tape.get())))),
+        ..Options::default()
+    };
+    run_opts("++T>+++T F-TF F", options, b"").unwrap();
+    // the last one is inside F's body, so its position is counted from there
+    assert_eq!(traps, [(2, 0, 2), (7, 1, 3), (1, 1, 2)]);
+    // without a callback it's a no-op
+    assert_eq!(run_opts("+T.", extended(), b"").unwrap(), [1]);
+}
+
+#[test]
+fn assert_command() {
+    // 2 + 3 worked out in the cell after the 5 it should equal
+    assert_eq!(run_opts("+++++>++>+++[-<+>]<<E>+.", extended(), b"").unwrap(), [6]);
+    let error = ExecError::AssertionFailed { index: 19, cell: 4, next: 5 };
+    assert_eq!(run_opts("++++>++>+++[-<+>]<<E.", extended(), b""), Err(error));
+}
+
+#[test]
+fn rotate_commands() {
+    // 129 is 0b1000_0001, a shift would lose one of the ends
+    let code = format!("{}Q.W.W.", "+".repeat(129));
+    assert_eq!(run_opts(&code, extended(), b"").unwrap(), [3, 129, 192]);
+    let code = format!("{}`.", "+".repeat(129));
+    assert_eq!(run_opts(&code, extended(), b"").unwrap(), [2]);
+}
+
+#[test]
+fn grid_commands() {
+    let grid = |width| Options { extensions: true, grid_width: width, ..Options::default() };
+    // down the diagonal of a 3x3 grid and back up
+    let code = "+V>++V>+++UU<<.V>.V>.";
+    assert_eq!(run_opts(code, grid(Some(3)), b"").unwrap(), [1, 2, 3]);
+    // without a width U and V don't move
+    assert_eq!(run_opts("+V+U.", grid(None), b"").unwrap(), [2]);
+}
+
+#[test]
+fn byte_literal_command() {
+    assert_eq!(run_opts("B000.B127.B255.", extended(), b"").unwrap(), [0, 127, 255]);
+    // the digits aren't run as commands, even inside a function
+    assert_eq!(run_opts("FB065.F F+.", extended(), b"").unwrap(), [65, 66]);
+    for (code, index) in [("+B256", 1), ("B12", 0), ("B1x2", 0), ("\"B\"B07 ", 3)] {
+        assert_eq!(run_opts(code, extended(), b""), Err(ExecError::InvalidByteLiteral { index }), "{}", code);
+    }
+    // skips and copies step over the whole literal, not just the 'B'
+    assert_eq!(run_opts("+#B065.", extended(), b"").unwrap(), [1]);
+    assert_eq!(run_opts("#B000+.", extended(), b"").unwrap(), [1]);
+    // with the tests' seed the '&' skips
+    assert_eq!(run_opts("&B065.", extended(), b"").unwrap(), [0]);
+    assert_eq!(run_opts("Z=B065+.", extended(), b"").unwrap(), [1]);
+    // in a string it's just a B
+    assert_eq!(run_opts("\"B1\".", extended(), b"").unwrap(), b"1");
+    // unless a skip lands on it, and then it isn't a literal
+    for code in ["+#\"Bx\"", "+#\"B\"", "+#^Bab^", "+#\"B065\""] {
+        assert_eq!(run_opts(code, extended(), b""), Err(ExecError::InvalidByteLiteral { index: 3 }), "{}", code);
+    }
+    // found by fuzzing, these used to panic
+    for code in ["F\"@FB&16", "\\_2S^_S\n&B"] {
+        let _ = run_opts(code, extended(), b"");
+    }
+}
+
+#[test]
+fn popcount_command() {
+    assert_eq!(run_opts("X.B255X.B170X.", extended(), b"").unwrap(), [0, 8, 4]);
+}
+
+#[test]
+fn sum_command() {
+    // 1 + 2 + 3 into a cell holding 3
+    assert_eq!(run_opts(">+>++>+++<<<+++A.", extended(), b"").unwrap(), [6]);
+    assert_eq!(run_opts(">-<++A.>.", extended(), b"").unwrap(), [255, 255]);
+}
+
+#[test]
+fn left_len_command() {
+    assert_eq!(run_opts("L.>>>L.<<L.", extended(), b"").unwrap(), [0, 3, 1]);
+    // going left first adds cells on that side
+    assert_eq!(run_opts("<<>L.", extended(), b"").unwrap(), [1]);
+    // far enough that it saturates
+    let far = format!("{}L.", ">".repeat(300));
+    assert_eq!(run_opts(&far, extended(), b"").unwrap(), [255]);
+}
+
+#[test]
+fn step_count_command() {
+    assert_eq!(run_opts("C.", extended(), b"").unwrap(), [1]);
+    // the spaces and the function body are steps too
+    assert_eq!(run_opts("F++F F C.", extended(), b"").unwrap(), [10]);
+    // a 255 round loop is well past what fits in a cell
+    assert_eq!(run_opts("-[-]C.", extended(), b"").unwrap(), [255]);
+    // loops run in one go still count each step
+    assert_eq!(run_opts("+++[-]C.", extended(), b"").unwrap(), [11]);
+    assert_eq!(run_opts("++[->+<]C.", extended(), b"").unwrap(), [14]);
+}
+
+#[test]
+fn rerun_printed_source() {
+    // ';' prints the source, then ' runs everything printed so far, which is the
+    // source again. The second time round the cell is 1 so it prints and returns
+    let code = "[.@]+;'";
+    let mut expected = code.as_bytes().to_vec();
+    expected.push(1);
+    assert_eq!(run(code, b"").unwrap(), expected);
+}
+
+#[test]
+fn dedupe_reruns() {
+    let dedupe = || Options { dedupe_reruns: true, ..Options::default() };
+    // output that's just ' would rerun itself forever
+    assert_eq!(run_opts("\"'\".'", dedupe(), b"").unwrap(), b"'");
+    // the second ' would run "+" again, making the cell '-' instead of ','
+    assert_eq!(run_opts("\"+\".''.", dedupe(), b"").unwrap(), b"+,");
+    assert_eq!(run("\"+\".''.", b"").unwrap(), b"+-");
+    // printing in between means the next ' has something new to run, here "+,"
+    assert_eq!(run_opts("\"+\".'.'.", dedupe(), b"x").unwrap(), b"+,x");
+}
+
+#[test]
+fn runtime_errors() {
+    assert_eq!(run("+>[", b""), Err(ExecError::MismatchedBrackets { index: 2 }));
+    assert_eq!(run("\"abc", b""), Err(ExecError::UnterminatedString { index: 0 }));
+    assert_eq!(run("+^abc", b""), Err(ExecError::UnterminatedComment { index: 1 }));
+    assert_eq!(run("++1", b""), Err(ExecError::Unimplemented { command: '1', index: 2 }));
+    assert_eq!(run("7", b""), Err(ExecError::Unimplemented { command: '7', index: 0 }));
+    // ' on output that isn't UTF-8
+    assert_eq!(run("-.'", b""), Err(ExecError::NonUtf8Output));
+    assert_eq!(ExecError::MismatchedBrackets { index: 1 }.to_string(), "mismatched brackets at 1");
+}
+
+#[test]
+fn allowed_commands() {
+    let classic = || Options { allowed_commands: Some("<>+-[].,"), ..Options::default() };
+    assert_eq!(run_opts("+[->+<]> ,.", classic(), b"a").unwrap(), b"a");
+    assert_eq!(run_opts("++?.", classic(), b""), Err(ExecError::Disallowed { command: '?', index: 2 }));
+    // functions aren't commands
+    assert_eq!(run_opts("F+.F F", classic(), b"").unwrap(), [1]);
+    // loops that normally run in one go are still checked command by command
+    let options = |allowed| Options { allowed_commands: Some(allowed), ..Options::default() };
+    assert_eq!(run_opts("+[-]", options("+[]"), b""), Err(ExecError::Disallowed { command: '-', index: 2 }));
+    assert_eq!(run_opts("+[->+<]", options("+-[]"), b""), Err(ExecError::Disallowed { command: '>', index: 3 }));
+}
+
+#[test]
+fn tolerate_unimplemented() {
+    let options = || Options { tolerate_unimplemented: true, ..Options::default() };
+    assert_eq!(run_opts("++1.7+.", options(), b"").unwrap(), [2, 3]);
+}
+
+#[test]
+fn round_to_next() {
+    let options = || Options { round_to_next: true, ..Options::default() };
+    let round = |n: u8, base: u8| run_opts(",>,<5.", options(), &[n, base]).unwrap()[0];
+    assert_eq!(round(14, 10), 10);
+    assert_eq!(round(15, 10), 10);
+    assert_eq!(round(16, 10), 20);
+    assert_eq!(round(7, 4), 8);
+    assert_eq!(round(6, 4), 4);
+    assert_eq!(round(200, 1), 200);
+    assert_eq!(round(130, 255), 255);
+    assert_eq!(round(127, 255), 0);
+    // a 0 next cell means 5, like without the option
+    assert_eq!(round(13, 0), 15);
+    assert_eq!(round(254, 0), 255);
+    // rounding up would pass 255, so it goes down instead
+    assert_eq!(round(251, 100), 200);
+    // without the option the next cell doesn't matter
+    assert_eq!(run(",>,<5.", &[14, 10]).unwrap(), [15]);
+}
+
+#[test]
+fn trace_inside_function() {
+    let mut trace = vec![];
+    let options = Options { trace: Some(Box::new(&mut trace)), ..Options::default() };
+    assert_eq!(run_opts("\n\nF\n+F F.", options, b"").unwrap(), [1]);
+    // the call runs the body where it was written, not from the start of the source
+    assert!(String::from_utf8(trace).unwrap().ends_with(
+        "::DEBUG:: running 'F' (idx 7, line 4, col 4)\n\
+         ::DEBUG:: running '\\n' (idx 3, line 3, col 2)\n\
+         ::DEBUG:: running '+' (idx 4, line 4, col 1)\n\
+         ::DEBUG:: running '.' (idx 8, line 4, col 5)\n"
+    ));
+}
+
+#[test]
+fn div_by_zero() {
+    assert_eq!(run("++>+<//.", b"").unwrap(), [2]);
+    // plain bf19 gives 255
+    assert_eq!(run("+/.", b"").unwrap(), [255]);
+    let options = Options { div_by_zero_error: true, ..Options::default() };
+    assert_eq!(run_opts("+/.", options, b""), Err(ExecError::DivByZero { index: 1 }));
+}
+
+#[test]
+fn step_limit() {
+    let limit = |max| Options { max_steps: Some(max), ..Options::default() };
+    assert_eq!(run_opts("+++.", limit(4), b"").unwrap(), [3]);
+    assert_eq!(run_opts("+++.", limit(3), b""), Err(ExecError::StepLimit));
+    assert_eq!(run_opts("+[]", limit(1000), b""), Err(ExecError::StepLimit));
+}
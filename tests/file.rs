@@ -0,0 +1,77 @@
+use bf19::{exec_capture, exec_capture_with, exec_file, exec_streaming, exec_string, ExecError, Options, Output, Tee};
+use std::{env, fs, path::PathBuf};
+
+// Write a source file to a temporary path unique to this test
+fn source_file(name: &str, contents: &[u8]) -> PathBuf {
+    let path = env::temp_dir().join(format!("bf19-{}-{}.bf19", name, std::process::id()));
+    fs::write(&path, contents).unwrap();
+    path
+}
+
+#[test]
+fn runs_file() {
+    let path = source_file("runs", b"\"hi\"<.>.");
+    assert_eq!(exec_file(&path).unwrap(), b"hi");
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn missing_file() {
+    assert!(matches!(exec_file("/nonexistent/bf19/source"), Err(ExecError::Io(_))));
+}
+
+#[test]
+fn non_utf8_file() {
+    let path = source_file("non-utf8", b"+\xff.");
+    assert_eq!(exec_file(&path), Err(ExecError::NonUtf8Source));
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn parse_error() {
+    let path = source_file("parse", b"+]");
+    assert_eq!(exec_file(&path), Err(ExecError::MismatchedBrackets { index: 1 }));
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn capture_and_string() {
+    let hi = "\"hi\"<.>.";
+    assert_eq!(exec_capture(hi).unwrap(), b"hi");
+    assert_eq!(exec_string(hi).unwrap(), "hi");
+    // 255 on its own isn't valid UTF-8
+    assert_eq!(exec_capture("-.").unwrap(), b"\xff");
+    assert_eq!(exec_string("-.").unwrap(), "\u{fffd}");
+    assert_eq!(exec_string("+]"), Err(ExecError::MismatchedBrackets { index: 1 }));
+}
+
+#[test]
+fn capture_with_echo() {
+    let hi = "\"hi\"<.>.";
+    for echo in [false, true] {
+        assert_eq!(exec_capture_with(hi, Options::default(), echo).unwrap(), b"hi");
+    }
+    let extended = Options { extensions: true, ..Options::default() };
+    assert_eq!(exec_capture_with("+++D<.", extended, true).unwrap(), [3]);
+}
+
+#[test]
+fn tee_writes_both() {
+    let (mut a, mut b) = (vec![], vec![]);
+    let mut tee = Tee(&mut a, &mut b);
+    tee.write_bytes(b"ab");
+    tee.write_bytes(b"c");
+    assert_eq!(a, b"abc");
+    assert_eq!(b, b"abc");
+}
+
+#[test]
+fn streaming() {
+    let mut bytes = vec![];
+    exec_streaming("\"hi\"<.>.+.", |b| bytes.push(b)).unwrap();
+    assert_eq!(bytes, b"hij");
+    // bytes printed before an error still arrive
+    let mut bytes = vec![];
+    assert_eq!(exec_streaming("+.1", |b| bytes.push(b)), Err(ExecError::Unimplemented { command: '1', index: 2 }));
+    assert_eq!(bytes, [1]);
+}
@@ -0,0 +1,187 @@
+use bf19::{build_index_table_for, check_commands, likely_nonterminating, max_nesting_depth, exec_with, ExecError, IndexBuilder, Options, Program};
+use rand::{rngs::StdRng, SeedableRng};
+
+#[test]
+fn incremental_matches_full_parse() {
+    let options = Options { string_escapes: true, ..Options::default() };
+    let sources = ["+[->[.]<]", "\"a\\\"]\"^[^%..%0+0", "[[]]\"[\"^\"^", ""];
+    for src in sources {
+        let full = Program::compile(src, &options).unwrap();
+        let mut builder = IndexBuilder::new(&options);
+        for c in src.chars() {
+            builder.push(c).unwrap();
+        }
+        assert!(builder.is_complete(), "{}", src);
+        assert_eq!(builder.len(), src.chars().count());
+        assert_eq!(builder.index_table(), full.index_table(), "{}", src);
+    }
+}
+
+#[test]
+fn incremental_completeness() {
+    let options = Options::default();
+    let mut builder = IndexBuilder::new(&options);
+    let mut complete = vec![];
+    for c in "+[\"]\"]^x^%%".chars() {
+        builder.push(c).unwrap();
+        complete.push(builder.is_complete());
+    }
+    assert_eq!(complete, [true, false, false, false, false, true, false, false, true, false, true]);
+}
+
+#[test]
+fn incremental_rejects_bad_char() {
+    let options = Options::default();
+    let mut builder = IndexBuilder::new(&options);
+    builder.extend(&['+', '[', ']']).unwrap();
+    assert_eq!(builder.push(']'), Err(ExecError::MismatchedBrackets { index: 3 }));
+    // the rejected char isn't kept, so the next one takes its place
+    assert_eq!(builder.len(), 3);
+    builder.push('.').unwrap();
+    assert_eq!(builder.finish().get_by_left(&1), Some(&2));
+}
+
+// Positions paired up when parsing src, sorted
+fn pairs(src: &str) -> Vec<(usize, usize)> {
+    let program = Program::compile(src, &Options::default()).unwrap();
+    let mut pairs: Vec<_> = program.index_table().iter().map(|(&l, &r)| (l, r)).collect();
+    pairs.sort();
+    pairs
+}
+
+#[test]
+fn comments_hide_pairs() {
+    // a lone ']' in a comment doesn't pop the '[' before it
+    assert_eq!(pairs("[^]^]"), [(0, 4), (1, 3)]);
+    assert_eq!(pairs("^[%0\"^"), [(0, 5)]);
+    assert_eq!(pairs("%^%^%"), [(0, 4), (1, 3)]);
+    assert_eq!(pairs("0^0^0"), [(0, 4), (1, 3)]);
+}
+
+#[test]
+fn strings_hide_pairs() {
+    assert_eq!(pairs("[\"]\"]"), [(0, 4), (1, 3)]);
+    assert_eq!(pairs("\"[%0\""), [(0, 4)]);
+    // a '^' in a string doesn't start a comment that would swallow the closing quote
+    assert_eq!(pairs("\"^\"^\"^"), [(0, 2), (3, 5)]);
+}
+
+#[test]
+fn hidden_pairs_when_run() {
+    let mut output = vec![];
+    let mut input: &[u8] = b"";
+    let mut rng = StdRng::seed_from_u64(19);
+    exec_with("+[^]^-]\"a^]\"<<.>.>.", Options::default(), &mut input, &mut output, &mut rng).unwrap();
+    assert_eq!(output, b"a^]");
+}
+
+#[test]
+fn slice_table_matches_shifted_whole() {
+    let options = Options::default();
+    let src: Vec<char> = "+F[->[.]<]%+%F F".chars().collect();
+    let whole = build_index_table_for(&src, &options).unwrap();
+    // the body of F, without the F's around it
+    let (start, end) = (2, 13);
+    let body = build_index_table_for(&src[start..end], &options).unwrap();
+    let mut shifted: Vec<_> = whole.iter()
+        .filter(|&(&l, _)| l >= start && l < end)
+        .map(|(&l, &r)| (l - start, r - start))
+        .collect();
+    shifted.sort();
+    let mut rebuilt: Vec<_> = body.iter().map(|(&l, &r)| (l, r)).collect();
+    rebuilt.sort();
+    assert_eq!(rebuilt, shifted);
+    assert_eq!(rebuilt, [(0, 7), (3, 5), (8, 10)]);
+    // a slice that leaves a ] without its [ is an error on its own
+    assert_eq!(build_index_table_for(&src[5..10], &options), Err(ExecError::MismatchedBrackets { index: 4 }));
+}
+
+fn check(code: &str) -> Vec<ExecError> {
+    check_commands(code, &Options::default()).err().unwrap_or_default()
+}
+
+#[test]
+fn check_commands_ok() {
+    assert_eq!(check("+[->+<]F.F F A=F"), []);
+    // only code counts, not strings or comments
+    assert_eq!(check("\"17=\"^1 7 =^"), []);
+}
+
+#[test]
+fn check_unimplemented() {
+    assert_eq!(check("+1.7"), [
+        ExecError::Unimplemented { command: '1', index: 1 },
+        ExecError::Unimplemented { command: '7', index: 3 },
+    ]);
+    let tolerant = Options { tolerate_unimplemented: true, ..Options::default() };
+    assert_eq!(check_commands("+1.7", &tolerant), Ok(()));
+}
+
+#[test]
+fn check_disallowed() {
+    let classic = Options { allowed_commands: Some("<>+-[].,"), ..Options::default() };
+    assert_eq!(check_commands("+? F.F \"?\"", &classic), Err(vec![
+        ExecError::Disallowed { command: '?', index: 1 },
+        ExecError::Disallowed { command: '"', index: 7 },
+    ]));
+}
+
+#[test]
+fn check_copy() {
+    assert_eq!(check("+=A"), [ExecError::MisplacedCopy { index: 1 }]);
+    assert_eq!(check("A=B=C"), [ExecError::MisplacedCopy { index: 3 }]);
+    assert_eq!(check("+A="), [ExecError::MisplacedCopy { index: 2 }]);
+    // the char after '=' is taken as a name even if it's a command
+    assert_eq!(check("A=1"), []);
+}
+
+#[test]
+fn check_reports_everything() {
+    assert_eq!(check("]1%+]0"), [
+        ExecError::MismatchedBrackets { index: 0 },
+        ExecError::Unimplemented { command: '1', index: 1 },
+        ExecError::MismatchedBrackets { index: 4 },
+        ExecError::UnmatchedPair { symbol: '%', index: 2 },
+        ExecError::UnmatchedPair { symbol: '0', index: 5 },
+    ]);
+}
+
+#[test]
+fn empty_source() {
+    assert!(Program::compile("", &Options::default()).unwrap().index_table().is_empty());
+    assert_eq!(Program::compile("", &Options::default()).unwrap().jump(0), None);
+    assert_eq!(check_commands("", &Options::default()), Ok(()));
+    assert!(IndexBuilder::new(&Options::default()).is_complete());
+}
+
+#[test]
+fn nonterminating() {
+    for code in ["+[.]", "+>++<[^ never ^.]", "++.|", ",[-]>\"hi\"<|", "[+]+|", "+[ ]"] {
+        assert!(likely_nonterminating(code), "{}", code);
+    }
+    // loops not entered, ones that change their cell, or a '|' after something
+    // that might end the program first
+    for code in ["[.]", "+-[.]", "+[-]", "+>[.]", "+.@|", "F@F F|", "\"|\"", "^|^", "+[.>]", "++[-]", ",[|]"] {
+        assert!(!likely_nonterminating(code), "{}", code);
+    }
+}
+
+#[test]
+fn check_byte_literals() {
+    let options = Options { extensions: true, ..Options::default() };
+    assert_eq!(check_commands("B255B000", &options), Ok(()));
+    let errors = vec![ExecError::InvalidByteLiteral { index: 0 }, ExecError::InvalidByteLiteral { index: 5 }];
+    assert_eq!(check_commands("B1x2 B9", &options), Err(errors));
+}
+
+#[test]
+fn nesting_depth() {
+    assert_eq!(max_nesting_depth(""), 0);
+    assert_eq!(max_nesting_depth("+++.>,."), 0);
+    assert_eq!(max_nesting_depth("[-][->+<][.]"), 1);
+    assert_eq!(max_nesting_depth("+[>[>[>[-]<]<]<[>]]"), 4);
+    // brackets in strings and comments don't count
+    assert_eq!(max_nesting_depth("[\"[[[\"]^[[[^"), 1);
+    // unfinished code as it's typed
+    assert_eq!(max_nesting_depth("][[+"), 2);
+}
@@ -0,0 +1,321 @@
+use bf19::{replay, ExecError, FnTable, HaltReason, Interpreter, Options, Program, Snapshot, Tape, TraceEntry};
+use rand::{rngs::StdRng, SeedableRng};
+
+#[test]
+fn need_input_and_resume() {
+    let program = Program::compile(",.,.", &Options::default()).unwrap();
+    let options = Options { need_input: true, ..Options::default() };
+    let mut input: &[u8] = b"a";
+    let mut output = vec![];
+    let mut rng = StdRng::seed_from_u64(19);
+    let mut interp = Interpreter::new(&program, options, &mut input, &mut output, &mut rng);
+    assert_eq!(interp.run(), Ok(HaltReason::NeedInput));
+    assert_eq!(interp.printed(), b"a");
+    // still waiting if nothing was fed
+    assert_eq!(interp.run(), Ok(HaltReason::NeedInput));
+    interp.feed_input(b"b");
+    assert_eq!(interp.run(), Ok(HaltReason::Finished));
+    drop(interp);
+    assert_eq!(output, b"ab");
+}
+
+#[test]
+fn run_until_output() {
+    // work out 6 * 7 before printing it, then print a newline
+    let program = Program::compile("++++++>+++++++<[->[->+>+<<]>>[-<<+>>]<<<]>>.>>++++++++++.", &Options::default()).unwrap();
+    let mut input: &[u8] = b"";
+    let mut output = vec![];
+    let mut rng = StdRng::seed_from_u64(19);
+    let mut interp = Interpreter::new(&program, Options::default(), &mut input, &mut output, &mut rng);
+    assert_eq!(interp.run_until_output(), Ok(Some(42)));
+    assert_eq!(interp.run_until_output(), Ok(Some(b'\n')));
+    assert_eq!(interp.run_until_output(), Ok(None));
+    drop(interp);
+    assert_eq!(output, b"*\n");
+}
+
+#[test]
+fn native_functions() {
+    // D doubles the cell, and beats the bf19 definition of D
+    let program = Program::compile("+++D.D.D+D D.", &Options::default()).unwrap();
+    let mut input: &[u8] = b"";
+    let mut output = vec![];
+    let mut rng = StdRng::seed_from_u64(19);
+    let mut interp = Interpreter::new(&program, Options::default(), &mut input, &mut output, &mut rng);
+    interp.register_native('D', Box::new(|tape: &mut Tape| tape.set(tape.get().wrapping_mul(2))));
+    assert_eq!(interp.run(), Ok(HaltReason::Finished));
+    assert_eq!(interp.printed(), [6, 12, 100]);
+}
+
+#[test]
+fn eof_reads_zero_by_default() {
+    let program = Program::compile(",.", &Options::default()).unwrap();
+    let mut input: &[u8] = b"";
+    let mut output = vec![];
+    let mut rng = StdRng::seed_from_u64(19);
+    let mut interp = Interpreter::new(&program, Options::default(), &mut input, &mut output, &mut rng);
+    assert_eq!(interp.run(), Ok(HaltReason::Finished));
+    assert_eq!(interp.printed(), [0]);
+}
+
+#[test]
+fn step_through_function_call() {
+    // F is defined by the first two F's and called by the third
+    let program = Program::compile("F+F F.", &Options::default()).unwrap();
+    let mut input: &[u8] = b"";
+    let mut output = vec![];
+    let mut rng = StdRng::seed_from_u64(19);
+    let mut interp = Interpreter::new(&program, Options::default(), &mut input, &mut output, &mut rng);
+    let mut steps = 0;
+    while interp.step().unwrap().is_none() {
+        steps += 1;
+    }
+    // F + F ' ' F, then the '+' inside F, then '.'
+    assert_eq!(steps, 7);
+    assert_eq!(interp.tape().get(), 1);
+    assert_eq!(interp.printed(), [1]);
+}
+
+#[test]
+fn output_remaining() {
+    let program = Program::compile("+.+.+.+.", &Options::default()).unwrap();
+    let options = Options { max_output: Some(3), ..Options::default() };
+    let mut input: &[u8] = b"";
+    let mut output = vec![];
+    let mut rng = StdRng::seed_from_u64(19);
+    let mut interp = Interpreter::new(&program, options, &mut input, &mut output, &mut rng);
+    assert_eq!(interp.output_remaining(), Some(3));
+    interp.step().unwrap();
+    interp.step().unwrap();
+    assert_eq!(interp.output_remaining(), Some(2));
+    assert_eq!(interp.run(), Err(ExecError::OutputLimit));
+    assert_eq!(interp.output_remaining(), Some(0));
+    drop(interp);
+    assert_eq!(output, [1, 2, 3]);
+}
+
+#[test]
+fn no_output_limit() {
+    let program = Program::compile("+.", &Options::default()).unwrap();
+    let mut input: &[u8] = b"";
+    let mut output = vec![];
+    let mut rng = StdRng::seed_from_u64(19);
+    let interp = Interpreter::new(&program, Options::default(), &mut input, &mut output, &mut rng);
+    assert_eq!(interp.output_remaining(), None);
+}
+
+#[test]
+fn shared_fntable() {
+    use std::sync::{Arc, Mutex};
+    let shared = Arc::new(Mutex::new(FnTable::new()));
+    let mut input: &[u8] = b"";
+    let mut rng = StdRng::seed_from_u64(19);
+
+    // A defines X, which clears the cell and adds one
+    let a = Program::compile("++++X[-]+X", &Options::default()).unwrap();
+    let mut table = shared.lock().unwrap();
+    let mut output = vec![];
+    let mut interp = Interpreter::new(&a, Options::default(), &mut input, &mut output, &mut rng)
+        .with_fntable(&mut table);
+    assert_eq!(interp.run(), Ok(HaltReason::Finished));
+    drop(interp);
+    drop(table);
+    assert!(shared.lock().unwrap().exists('X'));
+
+    // B calls it, with its brackets at different positions than in A
+    let b = Program::compile("+++++++X.", &Options::default()).unwrap();
+    let mut table = shared.lock().unwrap();
+    let mut output = vec![];
+    let mut interp = Interpreter::new(&b, Options::default(), &mut input, &mut output, &mut rng)
+        .with_fntable(&mut table);
+    assert_eq!(interp.run(), Ok(HaltReason::Finished));
+    drop(interp);
+    assert_eq!(output, [1]);
+}
+
+#[test]
+fn undefined_functions() {
+    let mut table = FnTable::new();
+    let mut input: &[u8] = b"";
+    let mut output = vec![];
+    let mut rng = StdRng::seed_from_u64(19);
+    let a = Program::compile("X+X", &Options::default()).unwrap();
+    let mut interp = Interpreter::new(&a, Options::default(), &mut input, &mut output, &mut rng)
+        .with_fntable(&mut table);
+    assert_eq!(interp.run(), Ok(HaltReason::Finished));
+    drop(interp);
+
+    // X is known so it can be called, but a typo for it is caught
+    let strict = || Options { define_on_unknown: false, ..Options::default() };
+    let b = Program::compile("XX.Y.", &strict()).unwrap();
+    let mut interp = Interpreter::new(&b, strict(), &mut input, &mut output, &mut rng)
+        .with_fntable(&mut table);
+    assert_eq!(interp.run(), Err(ExecError::UndefinedFunction { name: 'Y', index: 3 }));
+    drop(interp);
+    assert_eq!(output, [2]);
+}
+
+#[test]
+fn replay_recorded_run() {
+    // input, a string, a loop, a function, all the random commands and the grid
+    let code = ",>\"hi\"+++[->++<]F:>)F F?$4>>2{V+UB200<-R";
+    let program = Program::compile(code, &Options { extensions: true, ..Options::default() }).unwrap();
+    let mut trace: Vec<TraceEntry> = vec![];
+    let options = Options {
+        extensions: true,
+        grid_width: Some(2),
+        record: Some(Box::new(|entry| trace.push(entry))),
+        ..Options::default()
+    };
+    let mut input: &[u8] = b"xyz";
+    let mut output = vec![];
+    let mut rng = StdRng::seed_from_u64(19);
+    let mut interp = Interpreter::new(&program, options, &mut input, &mut output, &mut rng);
+    assert_eq!(interp.run(), Ok(HaltReason::Finished));
+    let expected = interp.tape().to_vec();
+    drop(interp);
+
+    assert_eq!(trace[0], TraceEntry { command: ',', idx: 0, bytes: vec![b'x'] });
+    assert_eq!(trace[2], TraceEntry { command: '"', idx: 2, bytes: b"hi".to_vec() });
+    assert_eq!(trace.last().unwrap().bytes, b"yz");
+    let mut tape = Tape::new();
+    replay(&trace, &mut tape);
+    assert_eq!(tape.to_vec(), expected);
+}
+
+#[test]
+fn fntable_to_source() {
+    let options = || Options { recursive_functions: true, ..Options::default() };
+    let mut input: &[u8] = b"";
+    let mut output = vec![];
+    let mut rng = StdRng::seed_from_u64(19);
+
+    let mut fntable = FnTable::new();
+    // brackets that only pair up across functions, a recursive call and a copy
+    let program = Program::compile("F[-.FF]F G+[G A.]A C=G", &options()).unwrap();
+    Interpreter::new(&program, options(), &mut input, &mut output, &mut rng)
+        .with_fntable(&mut fntable)
+        .run()
+        .unwrap();
+    let source = fntable.to_source();
+    // C is a copy of G so it comes with it, and A stays after the '[' its ']' matched
+    assert_eq!(source, "F[-.FF]FC+[CG+[GA.]A");
+
+    let mut copy = FnTable::new();
+    let program = Program::compile(&source, &options()).unwrap();
+    Interpreter::new(&program, options(), &mut input, &mut output, &mut rng)
+        .with_fntable(&mut copy)
+        .run()
+        .unwrap();
+    for name in ['A', 'C', 'F', 'G'] {
+        assert_eq!(copy.get(name).map(|f| &f.0), fntable.get(name).map(|f| &f.0));
+    }
+    assert_eq!(copy.to_source(), source);
+}
+
+#[test]
+fn fork() {
+    let program = Program::compile("+++F.F ,F>,F", &Options::default()).unwrap();
+    let mut input: &[u8] = b"a";
+    let mut output = vec![];
+    let mut rng = StdRng::seed_from_u64(19);
+    let mut interp = Interpreter::new(&program, Options::default(), &mut input, &mut output, &mut rng);
+    // run up to just after the first ','
+    for _ in 0..8 {
+        interp.step().unwrap();
+    }
+    assert_eq!(interp.tape().get(), b'a');
+
+    let mut fork_input: &[u8] = b"xy";
+    let mut fork_output = vec![];
+    let mut fork_rng = StdRng::seed_from_u64(19);
+    let mut fork = interp.fork(Options::default(), &mut fork_input, &mut fork_output, &mut fork_rng).unwrap();
+    assert_eq!(fork.tape().get(), b'a');
+    assert!(fork.fntable().exists('F'));
+    assert_eq!(fork.run(), Ok(HaltReason::Finished));
+    assert_eq!(fork.printed(), b"ax");
+    drop(fork);
+    assert_eq!(fork_output, b"ax");
+
+    // the original carries on from where the fork was made
+    assert_eq!(interp.tape().get(), b'a');
+    assert_eq!(interp.run(), Ok(HaltReason::Finished));
+    assert_eq!(interp.printed(), b"a\0");
+}
+
+#[test]
+fn snapshot_and_resume() {
+    let source = "F+.[-]F+++FF>,F.<.";
+    let program = Program::compile(source, &Options::default()).unwrap();
+    let run_straight = || {
+        let mut input: &[u8] = b"xy";
+        let mut output = vec![];
+        let mut rng = StdRng::seed_from_u64(19);
+        let mut interp = Interpreter::new(&program, Options::default(), &mut input, &mut output, &mut rng);
+        let mut steps = 0;
+        while interp.step().unwrap().is_none() {
+            steps += 1;
+        }
+        drop(interp);
+        (output, steps)
+    };
+    let (expected, total) = run_straight();
+
+    // stop after every possible step, including from inside F
+    for k in 0..total {
+        let mut input: &[u8] = b"xy";
+        let mut output = vec![];
+        let mut rng = StdRng::seed_from_u64(19);
+        let mut interp = Interpreter::new(&program, Options::default(), &mut input, &mut output, &mut rng);
+        for _ in 0..k {
+            interp.step().unwrap();
+        }
+        let bytes = interp.snapshot().to_bytes();
+        drop(interp);
+
+        let snapshot = Snapshot::from_bytes(&bytes).unwrap();
+        let mut rest_output = vec![];
+        let mut rest_rng = StdRng::seed_from_u64(19);
+        let mut resumed =
+            Interpreter::resume(snapshot, Options::default(), &mut input, &mut rest_output, &mut rest_rng).unwrap();
+        assert_eq!(resumed.run(), Ok(HaltReason::Finished));
+        assert_eq!(resumed.printed(), &expected[..]);
+        drop(resumed);
+        output.extend(rest_output);
+        assert_eq!(output, expected);
+    }
+
+    assert_eq!(Snapshot::from_bytes(b"bf19").err(), Some(ExecError::BadSnapshot));
+}
+
+#[test]
+fn mode_accessors() {
+    let program = Program::compile("_+_6+9F_+F F+", &Options::default()).unwrap();
+    let mut input: &[u8] = b"";
+    let mut output = vec![];
+    let mut rng = StdRng::seed_from_u64(19);
+    let mut interp = Interpreter::new(&program, Options::default(), &mut input, &mut output, &mut rng);
+    let mut modes = vec![(interp.is_bfmode(), interp.is_nicemode())];
+    while interp.step().unwrap().is_none() {
+        modes.push((interp.is_bfmode(), interp.is_nicemode()));
+    }
+    let (t, f) = (true, false);
+    assert_eq!(modes, [
+        (f, f), (t, f), (t, f), (f, f), (f, t), (f, t),
+        // defining F, then calling it: it switches bfmode on in its own frame,
+        // and that's gone when F returns
+        (f, f), (f, f), (f, f), (f, f), (f, f), (f, f), (f, f), (t, f), (f, f), (f, f),
+    ]);
+}
+
+#[test]
+fn byte_literal_needs_extensions_at_compile_time() {
+    // compiled without extensions the 'B' was never checked as a literal
+    let program = Program::compile("+B", &Options::default()).unwrap();
+    let options = Options { extensions: true, ..Options::default() };
+    let mut input: &[u8] = b"";
+    let mut output = vec![];
+    let mut rng = StdRng::seed_from_u64(19);
+    let mut interp = Interpreter::new(&program, options, &mut input, &mut output, &mut rng);
+    assert_eq!(interp.run(), Err(ExecError::InvalidByteLiteral { index: 1 }));
+}
@@ -0,0 +1,55 @@
+// Property tests over randomly generated tapes and programs. The cases come
+// from a fixed seed so failures are reproducible
+use bf19::{check_commands, random_program, Options, Program, Tape};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+const CASES: usize = 256;
+
+// Random tape with up to 20 cells, and the (cells, head) it should hold
+fn random_tape(rng: &mut StdRng) -> (Tape, Vec<u8>, usize) {
+    let len = rng.gen_range(1..=20);
+    let cells: Vec<u8> = (0..len).map(|_| rng.gen()).collect();
+    let head = rng.gen_range(0..len);
+    let mut tape = Tape::new();
+    for c in &cells[..head] { tape.insert_left(*c); }
+    for c in cells[head+1..].iter().rev() { tape.insert_right(*c); }
+    tape.set(cells[head]);
+    (tape, cells, head)
+}
+
+#[test]
+fn expand_n_collapse_n_round_trip() {
+    let mut rng = StdRng::seed_from_u64(0xbf19);
+    for n in 1..=3 {
+        for _ in 0..CASES {
+            let (mut tape, cells, head) = random_tape(&mut rng);
+            tape.expand_n(n);
+            assert_eq!(tape.to_vec().0.len(), cells.len() * n);
+            tape.collapse_n(n);
+            assert_eq!(tape.to_vec(), (cells, head));
+        }
+    }
+}
+
+#[test]
+fn expand_2_collapse_2_round_trip() {
+    let mut rng = StdRng::seed_from_u64(0xbf19);
+    for _ in 0..CASES {
+        let (mut tape, cells, head) = random_tape(&mut rng);
+        tape.expand_2();
+        assert_eq!(tape.to_vec().0.len(), cells.len() * 2);
+        tape.collapse_2();
+        assert_eq!(tape.to_vec(), (cells, head));
+    }
+}
+
+#[test]
+fn random_programs_parse() {
+    let mut rng = StdRng::seed_from_u64(0xbf19);
+    for len in 0..CASES {
+        let code = random_program(len, &mut rng);
+        assert!(code.len() >= len, "{:?}", code);
+        assert_eq!(check_commands(&code, &Options::default()), Ok(()), "{:?}", code);
+        assert!(Program::compile(&code, &Options::default()).is_ok(), "{:?}", code);
+    }
+}
@@ -0,0 +1,511 @@
+use bf19::{ExecError, Tape};
+use rand::{rngs::StdRng, SeedableRng};
+
+// Read `left` cells to the left of the head through `right` cells to the right,
+// leaving the head where it started
+fn window(tape: &mut Tape, left: usize, right: usize) -> Vec<u8> {
+    for _ in 0..left { tape.prev(); }
+    let mut cells = vec![];
+    for _ in 0..(left + right) {
+        cells.push(tape.get());
+        tape.next();
+    }
+    cells.push(tape.get());
+    for _ in 0..right { tape.prev(); }
+    cells
+}
+
+// Tape holding `cells` with the head on `cells[head]`
+fn tape_from(cells: &[u8], head: usize) -> Tape {
+    let mut tape = Tape::new();
+    for c in &cells[..head] { tape.insert_left(*c); }
+    for c in cells[head+1..].iter().rev() { tape.insert_right(*c); }
+    tape.set(cells[head]);
+    tape
+}
+
+#[test]
+fn expand_2_empty_right() {
+    let mut tape = tape_from(&[1, 2, 3], 2);
+    tape.expand_2();
+    assert_eq!(tape.get(), 3);
+    assert_eq!(window(&mut tape, 4, 2), vec![1, 1, 2, 2, 3, 3, 0]);
+}
+
+#[test]
+fn expand_2_empty_left() {
+    let mut tape = tape_from(&[1, 2, 3], 0);
+    tape.expand_2();
+    assert_eq!(tape.get(), 1);
+    assert_eq!(window(&mut tape, 1, 5), vec![0, 1, 1, 2, 2, 3, 3]);
+}
+
+#[test]
+fn expand_2_both_empty() {
+    let mut tape = tape_from(&[7], 0);
+    tape.expand_2();
+    assert_eq!(tape.get(), 7);
+    assert_eq!(tape.get_next(), 7);
+    assert_eq!(window(&mut tape, 1, 2), vec![0, 7, 7, 0]);
+}
+
+#[test]
+fn expand_2_middle() {
+    let mut tape = tape_from(&[1, 2, 3], 1);
+    tape.expand_2();
+    assert_eq!(window(&mut tape, 2, 3), vec![1, 1, 2, 2, 3, 3]);
+}
+
+#[test]
+fn expand_3_middle() {
+    let mut tape = tape_from(&[1, 2, 3], 1);
+    tape.expand_3();
+    assert_eq!(window(&mut tape, 3, 5), vec![1, 1, 1, 2, 2, 2, 3, 3, 3]);
+}
+
+#[test]
+fn swap_next() {
+    let mut tape = tape_from(&[1, 2, 3], 1);
+    tape.swap_next();
+    assert_eq!(window(&mut tape, 1, 1), vec![1, 3, 2]);
+    let mut tape = tape_from(&[5], 0);
+    tape.swap_next();
+    assert_eq!(window(&mut tape, 0, 1), vec![0, 5]);
+}
+
+#[test]
+fn to_vec() {
+    let tape = tape_from(&[1, 2, 3, 4], 1);
+    assert_eq!(tape.to_vec(), (vec![1, 2, 3, 4], 1));
+}
+
+#[test]
+fn rotate() {
+    let cells = [1, 2, 3, 4, 5];
+    for n in 0..12 {
+        let mut expected = cells.to_vec();
+        expected.rotate_right(n % cells.len());
+        let mut tape = tape_from(&cells, 1);
+        tape.rotate_right(n);
+        assert_eq!(tape.to_vec(), (expected.clone(), 1));
+        // head stays put, so it sees whatever rotated under it
+        assert_eq!(tape.get(), expected[1]);
+
+        let mut expected = cells.to_vec();
+        expected.rotate_left(n % cells.len());
+        let mut tape = tape_from(&cells, 3);
+        tape.rotate_left(n);
+        assert_eq!(tape.to_vec(), (expected, 3));
+    }
+}
+
+#[test]
+fn rotate_single_cell() {
+    let mut tape = tape_from(&[9], 0);
+    tape.rotate_right(3);
+    tape.rotate_left(1);
+    assert_eq!(tape.to_vec(), (vec![9], 0));
+}
+
+#[test]
+fn diff() {
+    let old = tape_from(&[1, 2, 3, 4], 1);
+    assert_eq!(old.diff(&tape_from(&[1, 2, 3, 4], 1)), vec![]);
+    // changes on both sides and at the head
+    assert_eq!(old.diff(&tape_from(&[9, 2, 3, 8], 1)), vec![(-1, 1, 9), (2, 4, 8)]);
+    assert_eq!(old.diff(&tape_from(&[1, 7, 3, 4], 1)), vec![(0, 2, 7)]);
+    // cells only one tape has accessed compare against 0
+    assert_eq!(old.diff(&tape_from(&[5, 1, 2, 3], 2)), vec![(-2, 0, 5), (2, 4, 0)]);
+}
+
+#[test]
+fn count_nonzero() {
+    assert_eq!(Tape::new().count_nonzero(), 0);
+    assert_eq!(tape_from(&[0, 3, 0, 0, 255, 1], 2).count_nonzero(), 3);
+    assert_eq!(tape_from(&[0, 3, 0, 0, 255, 1], 1).count_nonzero(), 3);
+}
+
+#[test]
+fn map_right() {
+    let mut tape = tape_from(&[1, 2, 3, 4], 1);
+    tape.map_right(2, |x| x + 1);
+    assert_eq!(tape.to_vec(), (vec![1, 3, 4, 4], 1));
+    // past the accessed region it extends with zeros
+    tape.map_right(5, |x| x.wrapping_add(10));
+    assert_eq!(tape.to_vec(), (vec![1, 13, 14, 14, 10, 10], 1));
+    tape.map_right(0, |_| 99);
+    assert_eq!(tape.to_vec(), (vec![1, 13, 14, 14, 10, 10], 1));
+}
+
+#[test]
+fn rotate_bits() {
+    let mut tape = Tape::new();
+    tape.set(0b1000_0001);
+    tape.rotate_bits_left();
+    assert_eq!(tape.get(), 0b0000_0011);
+    tape.rotate_bits_right();
+    tape.rotate_bits_right();
+    assert_eq!(tape.get(), 0b1100_0000);
+    // eight times round is back where it started
+    (0..8).for_each(|_| tape.rotate_bits_left());
+    assert_eq!(tape.get(), 0b1100_0000);
+}
+
+#[test]
+fn grid_moves() {
+    // a 3 wide grid, numbered in reading order
+    let mut tape = tape_from(&[1, 2, 3, 4, 5, 6, 7, 8, 9], 0);
+    tape.down(3);
+    assert_eq!(tape.get(), 4);
+    tape.next();
+    tape.down(3);
+    assert_eq!(tape.get(), 8);
+    tape.up(3);
+    tape.up(3);
+    assert_eq!(tape.get(), 2);
+    assert_eq!(tape.head_offset(), 1);
+    // above the top row is unaccessed space like anywhere else past the end
+    tape.up(3);
+    assert_eq!((tape.get(), tape.head_offset()), (0, -2));
+}
+
+#[test]
+fn popcount() {
+    for (cell, bits) in [(0, 0), (1, 1), (255, 8), (0b1010_1010, 4), (0b1000_0000, 1)] {
+        let mut tape = Tape::new();
+        tape.set(cell);
+        tape.popcount();
+        assert_eq!(tape.get(), bits, "{}", cell);
+    }
+}
+
+#[test]
+fn fold_accessed() {
+    let tape = tape_from(&[3, 0, 5, 2, 7], 2);
+    let weighted = tape.fold_accessed(0, |sum, offset, x| sum + offset * x as isize);
+    let (cells, head) = tape.to_vec();
+    let manual: isize = cells.iter().enumerate().map(|(i, &x)| (i as isize - head as isize) * x as isize).sum();
+    assert_eq!(weighted, manual);
+    assert_eq!(weighted, 10);
+    let offsets = tape.fold_accessed(vec![], |mut offsets, offset, _| {
+        offsets.push(offset);
+        offsets
+    });
+    assert_eq!(offsets, [-2, -1, 0, 1, 2]);
+    assert_eq!(Tape::new().fold_accessed(0, |n, _, _| n + 1), 1);
+}
+
+#[test]
+fn clear_right() {
+    let mut tape = tape_from(&[1, 2, 3, 4, 5], 1);
+    tape.clear_right(2);
+    assert_eq!(tape.to_vec(), (vec![1, 0, 0, 4, 5], 1));
+    // past the accessed region, still with the head where it was
+    tape.next();
+    tape.next();
+    tape.clear_right(4);
+    assert_eq!(tape.to_vec(), (vec![1, 0, 0, 0, 0, 0, 0], 3));
+    tape.clear_right(0);
+    assert_eq!(tape.to_vec(), (vec![1, 0, 0, 0, 0, 0, 0], 3));
+}
+
+#[test]
+fn initial_cell() {
+    let mut tape = Tape::with_initial_cell(255);
+    assert_eq!(tape.get(), 255);
+    assert_eq!(tape.get_next(), 255);
+    tape.prev();
+    assert_eq!(tape.get(), 255);
+    tape.next();
+    tape.next();
+    assert_eq!(tape.get(), 255);
+    assert_eq!(tape.delete_left(), 255);
+    assert_eq!(tape.delete_left(), 255);
+    assert_eq!(tape.delete_right(), 255);
+    tape.insert_right_initial();
+    assert_eq!(tape.get_next(), 255);
+    tape.map_right(3, |x| x);
+    assert_eq!(tape.to_vec(), (vec![255; 3], 0));
+}
+
+#[test]
+fn canonicalize() {
+    let mut tape = tape_from(&[0, 0, 1, 2, 0, 3, 0], 3);
+    tape.canonicalize();
+    assert_eq!(tape.to_vec(), (vec![1, 2, 0, 3], 1));
+    let mut tape = tape_from(&[0, 0, 0], 1);
+    tape.canonicalize();
+    assert_eq!(tape.to_vec(), (vec![0], 0));
+}
+
+#[test]
+fn checksum() {
+    let tape = tape_from(&[1, 2, 3], 1);
+    // same cells, one with extra zeros accessed around them
+    let mut padded = tape_from(&[0, 0, 1, 2, 3, 0], 3);
+    assert_eq!(tape.checksum(), padded.checksum());
+    padded.canonicalize();
+    assert_eq!(tape.checksum(), padded.checksum());
+    assert_eq!(Tape::new().checksum(), tape_from(&[0, 0], 1).checksum());
+
+    // a single changed cell or a moved head differs
+    assert_ne!(tape.checksum(), tape_from(&[1, 2, 4], 1).checksum());
+    assert_ne!(tape.checksum(), tape_from(&[1, 9, 3], 1).checksum());
+    assert_ne!(tape.checksum(), tape_from(&[1, 2, 3], 2).checksum());
+    assert_ne!(Tape::new().checksum(), Tape::with_initial_cell(1).checksum());
+}
+
+#[test]
+fn read_all_from() {
+    let mut tape = tape_from(&[7], 0);
+    let mut input: &[u8] = b"abc";
+    assert_eq!(tape.read_all_from(&mut input), 3);
+    assert_eq!(tape.to_vec(), (vec![7, b'a', b'b', b'c'], 3));
+    // already at EOF
+    assert_eq!(tape.read_all_from(&mut input), 0);
+    assert_eq!(tape.to_vec(), (vec![7, b'a', b'b', b'c'], 3));
+}
+
+#[test]
+fn write_at() {
+    let mut tape = tape_from(&[1, 2, 3], 1);
+    // across the head and past both ends
+    tape.write_at(-3, &[4, 5, 6, 7, 8, 9]);
+    assert_eq!(tape.to_vec(), (vec![4, 5, 6, 7, 8, 9], 3));
+    assert_eq!(tape.get(), 7);
+
+    let mut tape = Tape::new();
+    tape.write_at(2, &[1, 2]);
+    tape.write_at(-1, &[3]);
+    assert_eq!(tape.to_vec(), (vec![3, 0, 0, 1, 2], 1));
+    tape.write_at(5, &[]);
+    assert_eq!(tape.extent(), 5);
+}
+
+#[test]
+fn pair() {
+    let mut tape = tape_from(&[1, 2, 3], 0);
+    assert_eq!(tape.pair(), (1, 2));
+    tape.next();
+    assert_eq!(tape.pair(), (2, 3));
+    tape.next();
+    // past the accessed cells the next one reads as initial_cell
+    assert_eq!(tape.pair(), (3, 0));
+    tape.prev();
+    tape.prev();
+    tape.prev();
+    assert_eq!(tape.pair(), (0, 1));
+    assert_eq!(Tape::with_initial_cell(4).pair(), (4, 4));
+}
+
+#[test]
+fn try_expand() {
+    let mut tape = tape_from(&[1, 2, 3], 1);
+    tape.try_expand_2(Some(6)).unwrap();
+    assert_eq!(tape.to_vec(), (vec![1, 1, 2, 2, 3, 3], 2));
+    assert_eq!(tape.try_expand_3(Some(17)), Err(ExecError::TapeTooLarge));
+    assert_eq!(tape.try_expand_2(Some(11)), Err(ExecError::TapeTooLarge));
+    // left as it was
+    assert_eq!(tape.to_vec(), (vec![1, 1, 2, 2, 3, 3], 2));
+    tape.try_expand_3(None).unwrap();
+    assert_eq!(tape.extent(), 18);
+}
+
+#[test]
+fn next_cell() {
+    let mut tape = tape_from(&[1, 2, 3], 1);
+    assert_eq!(tape.get_next(), 3);
+    tape.set_next(9);
+    assert_eq!(tape.to_vec(), (vec![1, 2, 9], 1));
+    // at the right end the next cell gets accessed
+    let mut tape = tape_from(&[1, 2], 1);
+    assert_eq!(tape.get_next(), 0);
+    tape.set_next(9);
+    assert_eq!(tape.to_vec(), (vec![1, 2, 9], 1));
+}
+
+#[test]
+fn prev_cell() {
+    let mut tape = tape_from(&[1, 2, 3], 1);
+    assert_eq!(tape.get_prev(), 1);
+    tape.set_prev(9);
+    assert_eq!(tape.to_vec(), (vec![9, 2, 3], 1));
+    // at the left end the previous cell gets accessed
+    let mut tape = tape_from(&[2, 3], 0);
+    assert_eq!(tape.get_prev(), 0);
+    tape.set_prev(9);
+    assert_eq!(tape.to_vec(), (vec![9, 2, 3], 1));
+    assert_eq!(Tape::with_initial_cell(4).get_prev(), 4);
+}
+
+#[test]
+fn to_signed_vec() {
+    let tape = tape_from(&[0, 127, 128, 200, 255], 2);
+    assert_eq!(tape.to_signed_vec(), (vec![0, 127, -128, -56, -1], 2));
+}
+
+#[test]
+fn bitwise_not_all() {
+    let mut tape = tape_from(&[0, 1, 255, 0x0f], 1);
+    tape.bitwise_not_all();
+    assert_eq!(tape.to_vec(), (vec![255, 254, 0, 0xf0], 1));
+}
+
+#[test]
+fn head_offset_follows_moves() {
+    let mut tape = Tape::new();
+    assert_eq!(tape.head_offset(), 0);
+    let mut expected = 0;
+    for right in [true, true, false, false, false, false, true] {
+        if right { tape.next() } else { tape.prev() }
+        expected += if right { 1 } else { -1 };
+        assert_eq!(tape.head_offset(), expected);
+    }
+    assert_eq!(tape.head_offset(), -1);
+}
+
+#[test]
+fn seek_origin() {
+    let mut tape = Tape::new();
+    tape.set(1);
+    for _ in 0..5 { tape.next(); }
+    assert_eq!(tape.head_offset(), 5);
+    tape.seek_origin();
+    assert_eq!((tape.head_offset(), tape.get()), (0, 1));
+    tape.prev();
+    tape.prev();
+    tape.seek_origin();
+    assert_eq!((tape.head_offset(), tape.get()), (0, 1));
+}
+
+#[test]
+fn origin_follows_edits() {
+    let mut tape = Tape::new();
+    tape.set(1);
+    tape.next();
+    tape.next();
+    // a cell inserted between the head and the origin pushes it away
+    tape.insert_left(7);
+    tape.insert_right(8);
+    assert_eq!(tape.head_offset(), 3);
+    tape.delete_left();
+    tape.delete_left();
+    tape.delete_left();
+    // deleting the origin's own cell leaves it on the head
+    assert_eq!(tape.head_offset(), 0);
+
+    let mut tape = tape_from(&[1, 2], 0);
+    tape.next();
+    tape.expand_3();
+    assert_eq!(tape.head_offset(), 3);
+    tape.seek_origin();
+    assert_eq!(tape.get(), 1);
+    tape.next();
+    tape.next();
+    tape.next();
+    tape.next();
+    tape.collapse_2();
+    assert_eq!(tape.head_offset(), 2);
+}
+
+#[test]
+fn iter_accessed() {
+    for head in 0..4 {
+        let tape = tape_from(&[5, 6, 7, 8], head);
+        let (cells, head) = tape.to_vec();
+        let expected: Vec<(isize, u8)> = cells.iter().enumerate()
+            .map(|(i, &x)| (i as isize - head as isize, x))
+            .collect();
+        assert_eq!(tape.iter_accessed().collect::<Vec<_>>(), expected);
+    }
+    assert_eq!(Tape::new().iter_accessed().collect::<Vec<_>>(), [(0, 0)]);
+}
+
+#[test]
+fn reserve() {
+    let mut tape = Tape::new();
+    tape.reserve_right(100);
+    tape.reserve_left(30);
+    let (left, right) = tape.spare_capacity();
+    assert!(left >= 30 && right >= 100);
+    // moving uses up the room on that side
+    for _ in 0..40 {
+        tape.next();
+    }
+    assert!(tape.spare_capacity().1 >= 60);
+    assert_eq!(tape.head_offset(), 40);
+}
+
+#[test]
+fn read_cstring() {
+    let tape = tape_from(b"xhello\0world", 1);
+    assert_eq!(tape.read_cstring(), b"hello");
+    assert_eq!(tape.get(), b'h');
+    assert_eq!(tape_from(b"hello\0", 5).read_cstring(), b"");
+    // no 0 before the end of the accessed cells
+    let mut tape = Tape::with_initial_cell(7);
+    tape.set(1);
+    assert_eq!(tape.read_cstring(), [1]);
+}
+
+#[test]
+fn sum_right() {
+    let tape = tape_from(&[9, 3, 100, 200, 5], 1);
+    assert_eq!(tape.sum_right(0), 0);
+    assert_eq!(tape.sum_right(2), 44);
+    assert_eq!(tape.sum_right(3), 49);
+    // past the accessed cells
+    assert_eq!(tape.sum_right(10), 49);
+    let mut tape = Tape::with_initial_cell(2);
+    tape.next();
+    tape.set(1);
+    tape.prev();
+    assert_eq!(tape.sum_right(4), 7);
+    assert_eq!(tape.to_vec().0.len(), 2);
+}
+
+#[test]
+fn stack_lens() {
+    assert_eq!(Tape::new().stack_lens(), (0, 0));
+    let mut tape = tape_from(&[1, 2, 3, 4], 1);
+    assert_eq!(tape.stack_lens(), (1, 2));
+    tape.next();
+    assert_eq!(tape.stack_lens(), (2, 1));
+    tape.expand_2();
+    assert_eq!(tape.stack_lens(), (4, 3));
+}
+
+#[test]
+fn scan_right_nonzero() {
+    let tape = tape_from(&[0, 5, 0, 0, 0, 7, 0], 1);
+    assert_eq!(tape.scan_right_nonzero(), Some(4));
+    assert_eq!(tape.get(), 5);
+    assert_eq!(tape_from(&[0, 5, 0, 0, 0, 7, 0], 5).scan_right_nonzero(), None);
+    assert_eq!(tape_from(&[1, 2], 0).scan_right_nonzero(), Some(1));
+    assert_eq!(Tape::new().scan_right_nonzero(), None);
+}
+
+#[test]
+fn u16_be() {
+    let mut tape = tape_from(&[7, 1, 255, 9], 1);
+    assert_eq!(tape.read_u16_be(), 0x01ff);
+    tape.write_u16_be(0x0200);
+    assert_eq!(tape.to_vec(), (vec![7, 2, 0, 9], 1));
+    assert_eq!(tape.read_u16_be(), 0x0200);
+    // the next cell gets accessed if it wasn't
+    let mut tape = Tape::new();
+    tape.write_u16_be(0xabcd);
+    assert_eq!(tape.to_vec(), (vec![0xab, 0xcd], 0));
+}
+
+#[test]
+fn randomize_keeps_both_sides() {
+    let mut tape = Tape::new();
+    for _ in 0..4 { tape.next(); }
+    for _ in 0..3 { tape.prev(); }
+    // one cell on the left, three on the right
+    assert_eq!(tape.to_vec(), (vec![0; 5], 1));
+    tape.randomize(&mut StdRng::seed_from_u64(19));
+    let (cells, head) = tape.to_vec();
+    assert_eq!((cells.len(), head), (5, 1));
+}